@@ -0,0 +1,139 @@
+use super::commands::Command;
+use super::interpreter::Interpreter;
+use super::problem::{ProblemID, ProblemNumber};
+use super::report::{DayRecord, Report};
+use super::{report, Input, LineError, RunOptions, Session};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::io::BufRead;
+
+/// What is known about each line of an input, for editor support.
+pub struct Analysis {
+    pub errors: Vec<LineError>,
+    pub report: Result<Report, String>,
+    /// Completed dates, followed by the current one.
+    days: Vec<DayRecord>,
+    outline: Outline,
+}
+
+/// Where dates begin and problems are added and referred to, by line number. Only the
+/// lines of the analyzed input are recorded, not those of included files.
+#[derive(Default, Clone)]
+pub(crate) struct Outline {
+    dates: HashMap<u32, NaiveDate>,
+    definitions: HashMap<ProblemID, u32>,
+    references: HashMap<u32, Vec<ProblemID>>,
+    /// `penalty` lines, and the date each one is for.
+    penalties: Vec<(NaiveDate, u32)>,
+}
+
+impl Outline {
+    /// Forgets everything recorded after the first `lines` lines.
+    pub(crate) fn truncate(&mut self, lines: u32) {
+        self.dates.retain(|&line_num, _| line_num <= lines);
+        self.definitions.retain(|_, line_num| *line_num <= lines);
+        self.references.retain(|&line_num, _| line_num <= lines);
+        self.penalties.retain(|&(_, line_num)| line_num <= lines);
+    }
+
+    /// Called with each command before it is executed.
+    pub(crate) fn record(&mut self, line_num: u32, cmd: &Command, exec: &Interpreter) {
+        let ids = |list: &[ProblemNumber]| -> Vec<ProblemID> {
+            list.iter()
+                .filter_map(|number| exec.current_problem_id(number.clone()))
+                .collect()
+        };
+        match cmd {
+            Command::BeginDate(date) => {
+                self.dates.insert(line_num, *date);
+            }
+            Command::FinishedProblems(list)
+            | Command::NeedToFix(list)
+            | Command::Fixed(list)
+            | Command::Unadd(list) => {
+                self.references.insert(line_num, ids(list));
+            }
+            Command::Penalty => {
+                if let Some(date) = exec.current_date() {
+                    self.penalties.push((date, line_num));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Called with the problems an `add` on `line_num` added, once it has succeeded.
+    pub(crate) fn record_added<'a>(
+        &mut self,
+        line_num: u32,
+        added: impl Iterator<Item = &'a ProblemID>,
+    ) {
+        for id in added {
+            self.definitions.insert(id.clone(), line_num);
+        }
+    }
+}
+
+/// A date along with the totals up to and including it.
+pub struct DaySummary {
+    pub day: DayRecord,
+    /// Problems solved on this date and all the ones before it.
+    pub total_solved: u32,
+}
+
+impl Analysis {
+    pub(crate) fn new(
+        exec: &Interpreter,
+        errors: Vec<LineError>,
+        outline: Outline,
+        options: &RunOptions,
+    ) -> Self {
+        Analysis {
+            errors,
+            report: report(exec, options),
+            days: exec.days(),
+            outline,
+        }
+    }
+
+    /// The date begun on `line_num`, if any.
+    pub fn day(&self, line_num: u32) -> Option<DaySummary> {
+        let date = self.outline.dates.get(&line_num)?;
+        let position = self.days.iter().position(|day| day.date == *date)?;
+        Some(DaySummary {
+            day: self.days[position].clone(),
+            total_solved: self.days[..=position].iter().map(|day| day.solved).sum(),
+        })
+    }
+
+    /// The `penalty` lines for `date`.
+    pub fn penalty_lines(&self, date: NaiveDate) -> impl Iterator<Item = u32> + '_ {
+        self.outline
+            .penalties
+            .iter()
+            .filter(move |(penalty_date, _)| *penalty_date == date)
+            .map(|(_, line_num)| *line_num)
+    }
+
+    /// The line which added the problem `number` as it is referred to on `line_num`,
+    /// e.g. by `need to fix`.
+    pub fn definition(&self, line_num: u32, number: &ProblemNumber) -> Option<u32> {
+        let id = self
+            .outline
+            .references
+            .get(&line_num)?
+            .iter()
+            .find(|id| id.number == *number)?;
+        self.outline.definitions.get(id).copied()
+    }
+}
+
+/// Interprets `input` like `run_diagnostics`, also recording what each line does.
+pub fn analyze<T: BufRead>(input: Input<T>, options: &RunOptions) -> Analysis {
+    let mut outline = Outline::default();
+    let mut session = Session::new(options, false);
+    session.outline = Some(&mut outline);
+    session.interpret(vec![input]);
+    let (exec, errors) = (session.exec, session.errors);
+    Analysis::new(&exec, errors, outline, options)
+}
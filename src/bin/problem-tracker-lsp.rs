@@ -0,0 +1,407 @@
+//! A language server for problem-tracker logs, speaking LSP over the standard input and
+//! output.
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as RequestTrait};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, CompletionTextEdit,
+    Diagnostic, DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url,
+};
+use problem_tracker::{
+    Analysis, DaySummary, Incremental, Input, LineError, ProblemNumber, RunOptions,
+};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Every command, up to its first argument.
+const COMMANDS: &[&str] = &[
+    "set problem goal",
+    "set penalty",
+    "set book goal",
+    "set deadline",
+    "begin",
+    "begin chapter",
+    "begin book",
+    "assigned",
+    "finished",
+    "add",
+    "add even",
+    "add odd",
+    "need to fix",
+    "fixed",
+    "penalty",
+    "reset remaining",
+    "rest day",
+    "vacation",
+    "include",
+    "unadd",
+    "unfinish",
+];
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+    /// Keeps the state at recent dates, so that edits near the end are quick to analyze.
+    incremental: Incremental,
+}
+
+impl Document {
+    fn new(uri: &Url, text: String) -> Self {
+        let mut incremental = Incremental::new();
+        let analysis = Self::analyze(&mut incremental, uri, &text);
+        Document {
+            text,
+            analysis,
+            incremental,
+        }
+    }
+
+    fn update(&mut self, uri: &Url, text: String) {
+        self.analysis = Self::analyze(&mut self.incremental, uri, &text);
+        self.text = text;
+    }
+
+    fn analyze(incremental: &mut Incremental, uri: &Url, text: &str) -> Analysis {
+        incremental.analyze(
+            Input {
+                name: file_name(uri),
+                reader: text.as_bytes(),
+            },
+            &RunOptions::default(),
+        )
+    }
+
+    fn line(&self, line: u32) -> &str {
+        self.text.lines().nth(line as usize).unwrap_or("")
+    }
+
+    /// Errors on lines of this document; those in included files are left out, since
+    /// they are reported when the included file itself is open.
+    fn diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+        let name = file_name(uri);
+        self.analysis
+            .errors
+            .iter()
+            .filter_map(|error| {
+                let (file, line_num, start, end) = match error {
+                    LineError::ParseError {
+                        file,
+                        line_num,
+                        error,
+                        ..
+                    } => (file, *line_num, error.start, error.end),
+                    LineError::RuntimeError { file, line_num, .. } => {
+                        let line = self.line(line_num - 1);
+                        let start = line.len() - line.trim_start().len();
+                        (file, *line_num, start, line.trim_end().len())
+                    }
+                };
+                if *file != name {
+                    return None;
+                }
+                let line = self.line(line_num - 1);
+                let message = match error {
+                    LineError::ParseError { error, .. } => error.message(),
+                    LineError::RuntimeError { error, .. } => error.to_string(),
+                };
+                Some(Diagnostic {
+                    range: Range::new(
+                        Position::new(line_num - 1, utf16_column(line, start)),
+                        Position::new(line_num - 1, utf16_column(line, end.max(start))),
+                    ),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    source: Some("problem-tracker".to_owned()),
+                    message,
+                    ..Diagnostic::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Commands which start with what is typed so far on the line, if the cursor is still
+    /// within the keywords.
+    fn completion(&self, position: Position) -> Vec<CompletionItem> {
+        let line = self.line(position.line);
+        let typed = &line[..byte_offset(line, position.character)];
+        let indent = typed.len() - typed.trim_start().len();
+        let typed = typed.trim_start();
+        if !typed.chars().all(|c| c.is_ascii_alphabetic() || c == ' ') {
+            return Vec::new();
+        }
+        let range = Range::new(
+            Position::new(position.line, utf16_column(line, indent)),
+            position,
+        );
+        COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(typed))
+            .map(|command| CompletionItem {
+                label: (*command).to_owned(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    (*command).to_owned(),
+                ))),
+                ..CompletionItem::default()
+            })
+            .collect()
+    }
+
+    fn hover(&self, position: Position) -> Option<Hover> {
+        let summary = self.analysis.day(position.line + 1)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: hover_text(&summary),
+            }),
+            range: None,
+        })
+    }
+
+    /// The `add` line of the problem under the cursor.
+    fn definition(&self, uri: &Url, position: Position) -> Option<Location> {
+        let line = self.line(position.line);
+        let offset = byte_offset(line, position.character);
+        let is_label = |c: char| c.is_ascii_alphanumeric() || c == '.';
+        let start = line[..offset]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_label(c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let end = line[offset..]
+            .find(|c| !is_label(c))
+            .map_or(line.len(), |i| offset + i);
+        let number: ProblemNumber = line[start..end].parse().ok()?;
+        let line_num = self.analysis.definition(position.line + 1, &number)?;
+        let line = self.line(line_num - 1);
+        Some(Location::new(
+            uri.clone(),
+            Range::new(
+                Position::new(line_num - 1, 0),
+                Position::new(line_num - 1, utf16_column(line, line.len())),
+            ),
+        ))
+    }
+}
+
+fn hover_text(summary: &DaySummary) -> String {
+    let day = &summary.day;
+    let mut text = format!("**{}**\n\n", day.date);
+    if day.rest {
+        text.push_str("- Rest day\n");
+    } else {
+        text.push_str(&format!("- Assigned: {}\n", day.assigned));
+    }
+    text.push_str(&format!(
+        "- Solved: {} ({} in total)\n",
+        day.solved, summary.total_solved
+    ));
+    if let Some(total_remaining) = day.total_remaining {
+        text.push_str(&format!("- Remaining: {}\n", total_remaining));
+    }
+    if day.penalty > 0 {
+        text.push_str(&format!("- Penalty: {}\n", day.penalty));
+    }
+    text
+}
+
+fn file_name(uri: &Url) -> Option<String> {
+    uri.to_file_path()
+        .ok()
+        .map(|path| path.display().to_string())
+}
+
+/// Positions are counted in UTF-16 code units.
+fn utf16_column(line: &str, byte_offset: usize) -> u32 {
+    line[..byte_offset.min(line.len())]
+        .chars()
+        .map(|c| c.len_utf16() as u32)
+        .sum()
+}
+
+fn byte_offset(line: &str, utf16_column: u32) -> usize {
+    let mut column = 0;
+    for (offset, c) in line.char_indices() {
+        if column >= utf16_column {
+            return offset;
+        }
+        column += c.len_utf16() as u32;
+    }
+    line.len()
+}
+
+struct Server {
+    connection: Connection,
+    documents: HashMap<Url, Document>,
+}
+
+impl Server {
+    fn run(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for message in &self.connection.receiver.clone() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let Request { id, method, params } = request;
+        let result = match method.as_str() {
+            Completion::METHOD => request_params::<Completion>(params).and_then(|params| {
+                let position = params.text_document_position;
+                let items = self
+                    .documents
+                    .get(&position.text_document.uri)
+                    .map(|document| document.completion(position.position))
+                    .unwrap_or_default();
+                serde_json::to_value(CompletionResponse::Array(items))
+            }),
+            HoverRequest::METHOD => request_params::<HoverRequest>(params).and_then(|params| {
+                let position = params.text_document_position_params;
+                let hover = self
+                    .documents
+                    .get(&position.text_document.uri)
+                    .and_then(|document| document.hover(position.position));
+                serde_json::to_value(hover)
+            }),
+            GotoDefinition::METHOD => request_params::<GotoDefinition>(params).and_then(|params| {
+                let position = params.text_document_position_params;
+                let uri = &position.text_document.uri;
+                let location = self
+                    .documents
+                    .get(uri)
+                    .and_then(|document| document.definition(uri, position.position))
+                    .map(GotoDefinitionResponse::Scalar);
+                serde_json::to_value(location)
+            }),
+            _ => {
+                return self.send(Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("Unknown method {}", method),
+                ))
+            }
+        };
+        self.send(match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err(error) => Response::new_err(
+                id,
+                ErrorCode::InvalidParams as i32,
+                format!("Invalid parameters for {}: {}", method, error),
+            ),
+        })
+    }
+
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                if let Some(params) = notification_params::<DidOpenTextDocument>(notification) {
+                    let document = params.text_document;
+                    self.update(document.uri, document.text)?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                if let Some(mut params) = notification_params::<DidChangeTextDocument>(notification)
+                {
+                    // The whole text is sent on every change.
+                    if let Some(change) = params.content_changes.pop() {
+                        self.update(params.text_document.uri, change.text)?;
+                    }
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                if let Some(params) = notification_params::<DidCloseTextDocument>(notification) {
+                    let uri = params.text_document.uri;
+                    self.documents.remove(&uri);
+                    self.publish_diagnostics(uri, Vec::new())?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, uri: Url, text: String) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let document = match self.documents.remove(&uri) {
+            Some(mut document) => {
+                document.update(&uri, text);
+                document
+            }
+            None => Document::new(&uri, text),
+        };
+        let diagnostics = document.diagnostics(&uri);
+        self.documents.insert(uri.clone(), document);
+        self.publish_diagnostics(uri, diagnostics)
+    }
+
+    fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.to_owned(),
+                params,
+            )))?;
+        Ok(())
+    }
+
+    fn send(&self, response: Response) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+}
+
+fn request_params<R: RequestTrait>(params: serde_json::Value) -> serde_json::Result<R::Params> {
+    serde_json::from_value(params)
+}
+
+/// The parameters of `notification`, or `None` if they are invalid. There is no way to
+/// reply to a notification, so the error is only logged.
+fn notification_params<N: NotificationTrait>(notification: Notification) -> Option<N::Params> {
+    let Notification { method, params } = notification;
+    serde_json::from_value(params)
+        .map_err(|error| eprintln!("Invalid parameters for {}: {}", method, error))
+        .ok()
+}
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions::default()),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+    };
+    server.run()?;
+    drop(server);
+    io_threads.join()?;
+    Ok(())
+}
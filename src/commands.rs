@@ -1,19 +1,137 @@
+use super::problem::ProblemNumber;
 use chrono::NaiveDate;
+use std::fmt;
 
-#[derive(Debug)]
+/// A single line of a log, as executed by [`Interpreter`](crate::Interpreter). See the
+/// README for what each command does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Command {
+    /// `set problem goal <n>`
     SetProblemGoal(u32),
+    /// `set penalty <n>`
     SetPenalty(u32),
+    /// `set book goal <n>`
+    SetBookGoal(u32),
+    /// `set deadline <date>`
+    SetDeadline(NaiveDate),
+    /// `begin <date>`
     BeginDate(NaiveDate),
+    /// `begin chapter <n>`
     BeginChapter(u32),
+    /// `begin book "<name>"`
+    BeginBook(String),
+    /// `assigned <n> problems`
     AssignedAmount(u32),
+    /// `finished <n> problems`
     FinishedAmount(u32),
-    AddProblems(Vec<u32>, Option<Vec<u32>>),
-    AddEvenProblems(Vec<u32>, Option<Vec<u32>>),
-    AddOddProblems(Vec<u32>, Option<Vec<u32>>),
-    NeedToFix(Vec<u32>),
-    Fixed(Vec<u32>),
+    /// `finished <list>`
+    FinishedProblems(Vec<ProblemNumber>),
+    /// `add <list> [skipping <list>]`
+    AddProblems(Vec<ProblemNumber>, Option<Vec<ProblemNumber>>),
+    /// `add even <list> [skipping <list>]`
+    AddEvenProblems(Vec<ProblemNumber>, Option<Vec<ProblemNumber>>),
+    /// `add odd <list> [skipping <list>]`
+    AddOddProblems(Vec<ProblemNumber>, Option<Vec<ProblemNumber>>),
+    /// `need to fix <list>`
+    NeedToFix(Vec<ProblemNumber>),
+    /// `fixed <list>`
+    Fixed(Vec<ProblemNumber>),
+    /// `penalty`
     Penalty,
+    /// `reset remaining`
     ResetRemaining,
+    /// `rest day`
+    RestDay,
+    /// `vacation <date> to <date>`
+    Vacation(NaiveDate, NaiveDate),
+    /// `include "<path>"`. Includes are resolved while reading files, so the interpreter
+    /// rejects this command with [`InterpreterError::UnhandledInclude`](crate::InterpreterError::UnhandledInclude).
+    Include(String),
+    /// `unadd <list>`
+    Unadd(Vec<ProblemNumber>),
+    /// `unfinish <n>`
+    Unfinish(u32),
+    /// A blank line or a comment.
     NOP,
 }
+
+/// Writes the command in its canonical form, which parses back into the same command.
+/// `NOP` is written as an empty string.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SetProblemGoal(n) => write!(f, "set problem goal {}", n),
+            Self::SetPenalty(n) => write!(f, "set penalty {}", n),
+            Self::SetBookGoal(n) => write!(f, "set book goal {}", n),
+            Self::SetDeadline(date) => write!(f, "set deadline {}", date),
+            Self::BeginDate(date) => write!(f, "begin {}", date),
+            Self::BeginChapter(n) => write!(f, "begin chapter {}", n),
+            Self::BeginBook(name) => write!(f, "begin book \"{}\"", name),
+            Self::AssignedAmount(n) => write!(f, "assigned {} {}", n, problem_unit(*n)),
+            Self::FinishedAmount(n) => write!(f, "finished {} {}", n, problem_unit(*n)),
+            Self::FinishedProblems(list) => write!(f, "finished {}", ProblemList(list)),
+            Self::AddProblems(list, skip) => write_add(f, "add", list, skip),
+            Self::AddEvenProblems(list, skip) => write_add(f, "add even", list, skip),
+            Self::AddOddProblems(list, skip) => write_add(f, "add odd", list, skip),
+            Self::NeedToFix(list) => write!(f, "need to fix {}", ProblemList(list)),
+            Self::Fixed(list) => write!(f, "fixed {}", ProblemList(list)),
+            Self::Penalty => write!(f, "penalty"),
+            Self::ResetRemaining => write!(f, "reset remaining"),
+            Self::RestDay => write!(f, "rest day"),
+            Self::Vacation(start, end) => write!(f, "vacation {} to {}", start, end),
+            Self::Include(path) => write!(f, "include \"{}\"", path),
+            Self::Unadd(list) => write!(f, "unadd {}", ProblemList(list)),
+            Self::Unfinish(n) => write!(f, "unfinish {}", n),
+            Self::NOP => Ok(()),
+        }
+    }
+}
+
+fn problem_unit(n: u32) -> &'static str {
+    if n == 1 {
+        "problem"
+    } else {
+        "problems"
+    }
+}
+
+fn write_add(
+    f: &mut fmt::Formatter<'_>,
+    keyword: &str,
+    list: &[ProblemNumber],
+    skip: &Option<Vec<ProblemNumber>>,
+) -> fmt::Result {
+    write!(f, "{} {}", keyword, ProblemList(list))?;
+    if let Some(skip) = skip {
+        write!(f, " skipping {}", ProblemList(skip))?;
+    }
+    Ok(())
+}
+
+/// Writes a list of problems, collapsing runs of consecutive problems into ranges.
+struct ProblemList<'a>(&'a [ProblemNumber]);
+
+impl fmt::Display for ProblemList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut i = 0;
+        while i < self.0.len() {
+            let start = &self.0[i];
+            let mut end = i;
+            while end + 1 < self.0.len() && self.0[end].succ().as_ref() == Some(&self.0[end + 1]) {
+                end += 1;
+            }
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if end > i {
+                write!(f, "{}-{}", start, self.0[end])?;
+            } else {
+                write!(f, "{}", start)?;
+            }
+            i = end + 1;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
-use super::problem::{FixStatus, ProblemID};
+use super::problem::{FixStatus, ProblemID, ProblemNumber};
 use chrono::NaiveDate;
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
 use std::fmt;
 
-#[derive(Debug, Eq, PartialEq)]
+/// An error executing a command. See
+/// [`Interpreter::next_command`](crate::Interpreter::next_command) for what the command
+/// still does.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum InterpreterError {
     DateNotContiguous,
     AssignProblemsNoDate,
@@ -16,7 +22,27 @@ pub enum InterpreterError {
     FixStatusNotChanged(ProblemID, FixStatus),
     FixStatusProblemNotFound(ProblemID, FixStatus),
     FixStatusWithoutChapter(FixStatus),
-    UnexpectedSkipProblem(u32),
+    UnexpectedSkipProblem(ProblemNumber),
+    RestDayNoDate,
+    PenaltyOnRestDay(NaiveDate),
+    VacationEndsBeforeStart(NaiveDate, NaiveDate),
+    BookGoalWithoutBook,
+    FinishProblemsWithoutChapter,
+    FinishedProblemNotAdded(ProblemID),
+    ProblemAlreadySolved(ProblemID),
+    IncludeFailed {
+        path: String,
+        reason: String,
+    },
+    IncludeCycle(String),
+    UnhandledInclude,
+    /// A line of the input could not be read, e.g. because it is not valid UTF-8.
+    ReadFailed(String),
+    UnaddProblemsWithoutChapter,
+    UnaddedProblemNotAdded(ProblemID),
+    UnaddedProblemAlreadySolved(ProblemID),
+    NotEnoughProblemsToUnfinish,
+    UnfinishedProblemsAlreadyUsed(u32),
 }
 
 impl fmt::Display for InterpreterError {
@@ -59,8 +85,189 @@ impl fmt::Display for InterpreterError {
                 Self::UnexpectedSkipProblem(num) => {
                     format!("Unexpected skip: problem {}. Only problems within the same \"add\" statement can be skipped.",num)
                 }
+                Self::RestDayNoDate => "Cannot have rest day without setting date.".to_owned(),
+                Self::PenaltyOnRestDay(date) =>
+                    format!("Unexpected \"penalty\" command; {} is a rest day", date),
+                Self::VacationEndsBeforeStart(start, end) => format!(
+                    "Vacation cannot end on {} before it starts on {}.",
+                    end, start
+                ),
+                Self::BookGoalWithoutBook =>
+                    "Cannot set book goal without beginning book.".to_owned(),
+                Self::FinishProblemsWithoutChapter =>
+                    "Cannot finish problems by number without beginning chapter.".to_owned(),
+                Self::FinishedProblemNotAdded(problem) =>
+                    format!("{} has not been added; cannot mark as finished.", problem),
+                Self::ProblemAlreadySolved(problem) =>
+                    format!("{} has already been solved.", problem),
+                Self::IncludeFailed { path, reason } =>
+                    format!("Could not include {}: {}", path, reason),
+                Self::IncludeCycle(path) =>
+                    format!("Cannot include {}; it is already being included.", path),
+                Self::UnhandledInclude =>
+                    "\"include\" must be resolved before reaching the interpreter.".to_owned(),
+                Self::ReadFailed(reason) => format!("Could not read line: {}", reason),
+                Self::UnaddProblemsWithoutChapter =>
+                    "Cannot remove problems without beginning chapter.".to_owned(),
+                Self::UnaddedProblemNotAdded(problem) =>
+                    format!("{} has not been added; cannot remove it.", problem),
+                Self::UnaddedProblemAlreadySolved(problem) =>
+                    format!("{} has already been solved; cannot remove it.", problem),
+                Self::NotEnoughProblemsToUnfinish => "Not enough problems to unfinish.".to_owned(),
+                Self::UnfinishedProblemsAlreadyUsed(not_used) => format!(
+                    "Only {} solved problems have not been used for a past day's assignment; the others cannot be unfinished.",
+                    not_used
+                ),
             }
         )?;
         Ok(())
     }
 }
+
+/// Text which the grammar accepts but which has no meaning, such as a problem range like
+/// `17a-18c` whose ends don't belong to the same sequence, or a date like `2021-02-30`.
+/// Positions are byte offsets within the line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct InvalidValue {
+    pub start: usize,
+    pub end: usize,
+    /// What was expected instead, e.g. "a valid range".
+    pub expected: &'static str,
+}
+
+/// A line which could not be parsed, along with the byte span of the offending text
+/// within the line and the tokens which the grammar would have accepted there.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyntaxError {
+    pub start: usize,
+    pub end: usize,
+    /// `None` if the parser reached the end of the line.
+    pub found: Option<String>,
+    pub expected: Vec<String>,
+}
+
+impl SyntaxError {
+    pub fn new(error: ParseError<usize, Token<'_>, InvalidValue>, line: &str) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => {
+                let found = line[location..].chars().next().unwrap_or(' ');
+                SyntaxError {
+                    start: location,
+                    end: location + found.len_utf8(),
+                    found: Some(found.to_string()),
+                    expected: Vec::new(),
+                }
+            }
+            ParseError::UnrecognizedEOF { location, expected } => SyntaxError {
+                start: location,
+                end: location,
+                found: None,
+                expected: describe_expected(expected, line),
+            },
+            ParseError::UnrecognizedToken {
+                token: (start, Token(_, text), end),
+                expected,
+            } => {
+                let mut expected = describe_expected(expected, line);
+                if expected.is_empty() {
+                    // only a comment could have followed
+                    expected.push("end of line".to_owned());
+                }
+                SyntaxError {
+                    start,
+                    end,
+                    found: Some(text.to_owned()),
+                    expected,
+                }
+            }
+            ParseError::ExtraToken {
+                token: (start, Token(_, text), end),
+            } => SyntaxError {
+                start,
+                end,
+                found: Some(text.to_owned()),
+                expected: Vec::new(),
+            },
+            ParseError::User {
+                error:
+                    InvalidValue {
+                        start,
+                        end,
+                        expected,
+                    },
+            } => SyntaxError {
+                start,
+                end,
+                found: Some(line[start..end].to_owned()),
+                expected: vec![expected.to_owned()],
+            },
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let found = match &self.found {
+            Some(text) => format!("`{}`", text),
+            None => "end of line".to_owned(),
+        };
+        match self.expected.as_slice() {
+            [] => format!("unexpected {}", found),
+            [only] => format!("expected {}, found {}", only, found),
+            [first, second] => format!("expected {} or {}, found {}", first, second, found),
+            [rest @ .., last] => format!(
+                "expected one of {}, or {}, found {}",
+                rest.join(", "),
+                last,
+                found
+            ),
+        }
+    }
+
+    /// Renders the error in the style of rustc, with a caret under the offending text.
+    pub fn render(&self, file: Option<&str>, line_num: u32, line: &str) -> String {
+        let column = line[..self.start].chars().count();
+        let width = line[self.start..self.end].chars().count().max(1);
+        let gutter = " ".repeat(line_num.to_string().len());
+        let location = match file {
+            Some(file) => format!("{}:{}:{}", file, line_num, column + 1),
+            None => format!("line {}:{}", line_num, column + 1),
+        };
+        format!(
+            "error: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}",
+            self.message(),
+            gutter,
+            location,
+            gutter,
+            line_num,
+            line,
+            gutter,
+            " ".repeat(column),
+            "^".repeat(width)
+        )
+    }
+}
+
+fn describe_expected(expected: Vec<String>, line: &str) -> Vec<String> {
+    // the same quoted string token is used for book names and included paths
+    let quoted = if line.trim_start().starts_with("include") {
+        "a quoted path"
+    } else {
+        "a quoted name"
+    };
+    expected
+        .into_iter()
+        .filter_map(|token| match token.as_str() {
+            // comments may end any line, so suggesting one is never helpful
+            r##"r#"#.*"#"## => None,
+            r##"r#"\\d+"#"## => Some("a number".to_owned()),
+            "Label" => Some("a problem label".to_owned()),
+            r##"r#"\"[^\"]*\""#"## => Some(quoted.to_owned()),
+            _ => Some(
+                if token.len() >= 2 && token.starts_with('"') && token.ends_with('"') {
+                    format!("`{}`", &token[1..token.len() - 1])
+                } else {
+                    token
+                },
+            ),
+        })
+        .collect()
+}
@@ -0,0 +1,70 @@
+use super::analysis::analyze;
+use super::commands::Command;
+use super::{Input, InterpreterError, LineError, RunOptions};
+use std::io::BufRead;
+
+/// A change to a log which fixes an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Suggestion {
+    /// Insert `line` before line `line_num`.
+    Insert { line_num: u32, line: String },
+    /// Remove line `line_num`.
+    Remove { line_num: u32 },
+}
+
+/// Suggests a fix for every missing or unexpected `penalty` in `input`: a `penalty` line
+/// at the end of a date whose assigned work was not completed, or removing the `penalty`
+/// lines of a date whose work was completed. Errors in included files are left alone.
+pub fn suggest_fixes<T: BufRead>(input: Input<T>) -> Vec<Suggestion> {
+    let name = input.name.clone();
+    let analysis = analyze(input, &RunOptions::default());
+    let mut suggestions = Vec::new();
+    for error in &analysis.errors {
+        match error {
+            LineError::RuntimeError {
+                file,
+                line_num,
+                error: InterpreterError::MissingPenalty(_),
+            } if *file == name => suggestions.push(Suggestion::Insert {
+                line_num: *line_num,
+                line: Command::Penalty.to_string(),
+            }),
+            LineError::RuntimeError {
+                file,
+                error: InterpreterError::UnexpectedPenalty(date),
+                ..
+            } if *file == name => suggestions.extend(
+                analysis
+                    .penalty_lines(*date)
+                    .map(|line_num| Suggestion::Remove { line_num }),
+            ),
+            _ => {}
+        }
+    }
+    suggestions
+}
+
+/// Applies `suggestions` to `text`, keeping its other lines (and their line endings) as
+/// they are. Inserted lines end with `\r\n` if any line of `text` does.
+pub fn apply_suggestions(text: &str, suggestions: &[Suggestion]) -> String {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut output = String::new();
+    for (line, line_num) in text.split_inclusive('\n').zip(1..) {
+        for suggestion in suggestions {
+            match suggestion {
+                Suggestion::Insert {
+                    line_num: before,
+                    line,
+                } if *before == line_num => {
+                    output.push_str(line);
+                    output.push_str(newline);
+                }
+                _ => {}
+            }
+        }
+        if !suggestions.contains(&Suggestion::Remove { line_num }) {
+            output.push_str(line);
+        }
+    }
+    output
+}
@@ -0,0 +1,58 @@
+use super::commands::Command;
+use super::parser;
+use super::{Input, InterpreterError, LineError, SyntaxError};
+use std::io::BufRead;
+
+/// Re-emits every line of `input` in its canonical form: keywords separated by single
+/// spaces, consecutive problems collapsed into ranges, and comments written as `# text`.
+/// Blank lines and comments are kept. Fails with every line that does not parse.
+pub fn format_log<T: BufRead>(input: Input<T>) -> Result<String, Vec<LineError>> {
+    let mut output = String::new();
+    let mut errors = Vec::new();
+    for (line, line_num) in input.reader.lines().zip(1..) {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                errors.push(LineError::RuntimeError {
+                    file: input.name.clone(),
+                    line_num,
+                    error: InterpreterError::ReadFailed(error.to_string()),
+                });
+                break;
+            }
+        };
+        match parser::CommentedCommandParser::new().parse(&line) {
+            Ok((cmd, comment)) => {
+                output.push_str(&format_line(&cmd, comment.as_deref()));
+                output.push('\n');
+            }
+            Err(error) => {
+                let error = SyntaxError::new(error, &line);
+                errors.push(LineError::ParseError {
+                    file: input.name.clone(),
+                    line_num,
+                    line,
+                    error,
+                });
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+fn format_line(cmd: &Command, comment: Option<&str>) -> String {
+    let comment = comment.map(|text| match text.trim() {
+        "" => "#".to_owned(),
+        text => format!("# {}", text),
+    });
+    match (cmd, comment) {
+        (Command::NOP, None) => String::new(),
+        (Command::NOP, Some(comment)) => comment,
+        (cmd, None) => cmd.to_string(),
+        (cmd, Some(comment)) => format!("{} {}", cmd, comment),
+    }
+}
@@ -0,0 +1,119 @@
+use super::analysis::{Analysis, Outline};
+use super::snapshot::{LineHasher, Snapshot};
+use super::{read_lines, report, Diagnostics, Input, Interpreter, LineError, RunOptions, Session};
+use chrono::NaiveDate;
+use std::io::BufRead;
+
+/// Number of checkpoints kept, for the most recent dates.
+const MAX_CHECKPOINTS: usize = 32;
+
+/// The state before a date was begun, along with the errors up to there.
+pub(crate) struct Checkpoint {
+    pub(crate) snapshot: Snapshot,
+    pub(crate) errors: Vec<LineError>,
+}
+
+pub(crate) struct Checkpoints(Vec<Checkpoint>);
+
+impl Checkpoints {
+    pub(crate) fn push(&mut self, checkpoint: Checkpoint) {
+        if self.0.len() == MAX_CHECKPOINTS {
+            self.0.remove(0);
+        }
+        self.0.push(checkpoint);
+    }
+
+    /// Drops the checkpoints which are no longer valid for `text`, and returns the last
+    /// one which still is. Only the checkpoints before the returned one are kept, since
+    /// the returned one is recorded again when resuming from it.
+    fn resume_point(&mut self, text: &str, as_of: Option<NaiveDate>) -> Option<Checkpoint> {
+        let mut lines = text.lines();
+        let mut lines_read = 0;
+        let mut hash = LineHasher::new();
+        let valid = self
+            .0
+            .iter()
+            .take_while(|checkpoint| {
+                while lines_read < checkpoint.snapshot.lines {
+                    match lines.next() {
+                        Some(line) => hash.write_line(line),
+                        None => return false,
+                    }
+                    lines_read += 1;
+                }
+                let past_as_of = match (as_of, checkpoint.snapshot.interpreter.current_date()) {
+                    (Some(as_of), Some(date)) => date > as_of,
+                    _ => false,
+                };
+                checkpoint.snapshot.hasher() == hash && !past_as_of
+            })
+            .count();
+        self.0.truncate(valid);
+        self.0.pop()
+    }
+}
+
+/// Interprets a log which changes over time, such as a file being edited. Each run
+/// starts from the last date begun before the first changed line, rather than from the
+/// top, so appending to the log only interprets the new lines again.
+///
+/// Only the log itself is checked for changes, not the files it includes.
+pub struct Incremental {
+    checkpoints: Checkpoints,
+    outline: Outline,
+}
+
+impl Default for Incremental {
+    fn default() -> Self {
+        Incremental {
+            checkpoints: Checkpoints(Vec::new()),
+            outline: Outline::default(),
+        }
+    }
+}
+
+impl Incremental {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `run_diagnostics_inputs` with a single input.
+    pub fn run<T: BufRead>(&mut self, input: Input<T>, options: &RunOptions) -> Diagnostics {
+        let (exec, errors) = self.interpret(input, options);
+        Diagnostics {
+            report: report(&exec, options),
+            errors,
+        }
+    }
+
+    /// Like `analyze`.
+    pub fn analyze<T: BufRead>(&mut self, input: Input<T>, options: &RunOptions) -> Analysis {
+        let (exec, errors) = self.interpret(input, options);
+        Analysis::new(&exec, errors, self.outline.clone(), options)
+    }
+
+    fn interpret<T: BufRead>(
+        &mut self,
+        input: Input<T>,
+        options: &RunOptions,
+    ) -> (Interpreter, Vec<LineError>) {
+        let (text, read_error) = read_lines(input.reader, input.name.as_deref());
+        let mut session = Session::new(options, false);
+        match self.checkpoints.resume_point(&text, options.as_of) {
+            Some(checkpoint) => {
+                session.resume(&checkpoint.snapshot);
+                session.errors = checkpoint.errors;
+                self.outline.truncate(checkpoint.snapshot.lines);
+            }
+            None => self.outline = Outline::default(),
+        }
+        session.checkpoints = Some(&mut self.checkpoints);
+        session.outline = Some(&mut self.outline);
+        session.interpret(vec![Input {
+            name: input.name,
+            reader: text.as_bytes(),
+        }]);
+        session.errors.extend(read_error);
+        (session.exec, session.errors)
+    }
+}
@@ -3,19 +3,25 @@ mod test;
 
 use super::commands::Command;
 use super::error::InterpreterError;
-use super::problem::{FixStatus, Problem, ProblemID};
-use super::report::Report;
+use super::problem::{FixStatus, Problem, ProblemID, ProblemNumber};
+use super::report::{self, BookReport, ChapterReport, Consistency, DayRecord, Report};
 use chrono::NaiveDate;
-use std::collections::{HashSet, VecDeque};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct DailyInformation {
     date: NaiveDate,
     assigned: u32,
     penalty: bool,
+    rest: bool,
+    solved: u32,
+    /// Problems per day needed to reach the goal by the deadline, as of the start of
+    /// this date.
+    required_pace: Option<u32>,
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 struct Problems {
     unsolved: VecDeque<ProblemID>,
     solved_not_used: VecDeque<Problem>,
@@ -25,15 +31,18 @@ struct Problems {
 }
 
 impl Problems {
-    pub fn add<I: Iterator<Item = ProblemID>>(&mut self, list: I) -> Result<(), InterpreterError> {
-        for problem in list {
-            if !self.all_added.contains(&problem) {
-                self.all_added.insert(problem.clone());
-                self.unsolved.push_back(problem);
-            } else {
-                return Err(InterpreterError::DuplicateProblemAdded(problem));
+    /// Adds every problem in `list`, or none of them if any has been added before.
+    pub fn add(&mut self, list: Vec<ProblemID>) -> Result<(), InterpreterError> {
+        let mut seen = HashSet::new();
+        for problem in &list {
+            if self.all_added.contains(problem) || !seen.insert(problem) {
+                return Err(InterpreterError::DuplicateProblemAdded(problem.clone()));
             }
         }
+        for problem in list {
+            self.all_added.insert(problem.clone());
+            self.unsolved.push_back(problem);
+        }
         Ok(())
     }
     pub fn finished(&mut self, n: u32) -> Result<(), InterpreterError> {
@@ -49,11 +58,66 @@ impl Problems {
             Err(InterpreterError::NotEnoughProblems)
         }
     }
+    /// Solves every problem in `list`, or none of them if any is not unsolved.
+    pub fn finished_specific(&mut self, list: Vec<ProblemID>) -> Result<(), InterpreterError> {
+        let mut seen = HashSet::new();
+        for problem in &list {
+            if !self.unsolved.contains(problem) || !seen.insert(problem) {
+                return Err(if self.all_added.contains(problem) {
+                    InterpreterError::ProblemAlreadySolved(problem.clone())
+                } else {
+                    InterpreterError::FinishedProblemNotAdded(problem.clone())
+                });
+            }
+        }
+        for problem in list {
+            self.unsolved.retain(|p| *p != problem);
+            self.solved_not_used.push_back(Problem {
+                id: problem,
+                fix_status: FixStatus::Fixed,
+            });
+        }
+        Ok(())
+    }
+    /// Removes problems which have been added but not solved.
+    pub fn unadd(&mut self, list: &[ProblemID]) -> Result<(), InterpreterError> {
+        for problem in list {
+            if !self.unsolved.contains(problem) {
+                return Err(if self.all_added.contains(problem) {
+                    InterpreterError::UnaddedProblemAlreadySolved(problem.clone())
+                } else {
+                    InterpreterError::UnaddedProblemNotAdded(problem.clone())
+                });
+            }
+        }
+        for problem in list {
+            self.unsolved.retain(|p| p != problem);
+            self.all_added.remove(problem);
+        }
+        Ok(())
+    }
+    /// Moves the last `n` solved problems back to the front of the unsolved ones. Only
+    /// problems which have not been used for an assignment yet can be moved.
+    pub fn unfinish(&mut self, n: u32) -> Result<(), InterpreterError> {
+        let n = n as usize;
+        if n > self.solved_not_used.len() {
+            return Err(if n <= self.total_solved() as usize {
+                InterpreterError::UnfinishedProblemsAlreadyUsed(self.total_solved_not_used())
+            } else {
+                InterpreterError::NotEnoughProblemsToUnfinish
+            });
+        }
+        let start = self.solved_not_used.len() - n;
+        for problem in self.solved_not_used.drain(start..).rev() {
+            self.unsolved.push_front(problem.id);
+        }
+        Ok(())
+    }
     pub fn solved_iter_mut(
         &mut self,
     ) -> std::iter::Chain<
-        std::collections::vec_deque::IterMut<Problem>,
-        std::collections::vec_deque::IterMut<Problem>,
+        std::collections::vec_deque::IterMut<'_, Problem>,
+        std::collections::vec_deque::IterMut<'_, Problem>,
     > {
         self.solved_used
             .iter_mut()
@@ -63,11 +127,15 @@ impl Problems {
     pub fn solved_iter(
         &self,
     ) -> std::iter::Chain<
-        std::collections::vec_deque::Iter<Problem>,
-        std::collections::vec_deque::Iter<Problem>,
+        std::collections::vec_deque::Iter<'_, Problem>,
+        std::collections::vec_deque::Iter<'_, Problem>,
     > {
         self.solved_used.iter().chain(self.solved_not_used.iter())
     }
+    /// Like `solved_iter`, but also includes problems solved before `reset remaining`.
+    pub fn all_solved_iter(&self) -> impl Iterator<Item = &Problem> {
+        self.solved_before_reset.iter().chain(self.solved_iter())
+    }
     pub fn use_problems(&mut self, n: u32) -> bool {
         let n = n as usize;
         if n <= self.solved_not_used.len() {
@@ -90,19 +158,69 @@ impl Problems {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Book {
+    name: String,
+    goal: Option<u32>,
+}
+
+/// Executes commands one at a time, keeping track of the problems and dates seen so far.
+///
+/// Commands are usually parsed from a log with [`parse_command`](crate::parse_command),
+/// but can also be built directly.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Interpreter {
     problem_goal: Option<u32>,
     penalty: Option<u32>,
+    deadline: Option<NaiveDate>,
     current_date: Option<DailyInformation>,
     current_chapter: Option<u32>,
+    /// Index into `books`
+    current_book: Option<usize>,
+    books: Vec<Book>,
 
     problems: Problems,
 
     total_penalty: u32,
+
+    /// One record for every date which has been completed, i.e. followed by another date.
+    history: Vec<DayRecord>,
 }
 
 impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn problem_goal(&self) -> Option<u32> {
+        self.problem_goal
+    }
+    pub fn penalty(&self) -> Option<u32> {
+        self.penalty
+    }
+    pub fn deadline(&self) -> Option<NaiveDate> {
+        self.deadline
+    }
+    /// The date begun last, if any.
+    pub fn current_date(&self) -> Option<NaiveDate> {
+        self.current_date
+            .as_ref()
+            .map(|current_date| current_date.date)
+    }
+    pub fn current_chapter(&self) -> Option<u32> {
+        self.current_chapter
+    }
+    /// The name of the book begun last, if any.
+    pub fn current_book(&self) -> Option<&str> {
+        self.current_book
+            .map(|index| self.books[index].name.as_str())
+    }
+    /// Problems which have been added but not solved yet, in the order they will be
+    /// finished by `finished <n> problems`.
+    pub fn unsolved_problems(&self) -> impl Iterator<Item = &ProblemID> {
+        self.problems.unsolved.iter()
+    }
+    /// The report as of the current state. Fails if no problem goal is set or no date
+    /// has begun.
     pub fn report(&self) -> Result<Report, &str> {
         if let Some(problem_goal) = self.problem_goal {
             if let Some(current_date) = &self.current_date {
@@ -118,12 +236,15 @@ impl Interpreter {
                 let unsolved_problems: Vec<ProblemID> =
                     self.problems.unsolved.iter().cloned().collect();
                 let total_solved = self.problems.total_solved();
-                let total_remaining =
-                    problem_goal + total_penalty - total_solved + total_need_to_fix;
+                let total_remaining = self.total_remaining(problem_goal);
                 let assigned = current_date.assigned;
                 let total_solved_not_used = self.problems.total_solved_not_used();
-                let assigned_problems_are_completed = assigned <= total_solved_not_used;
+                let assigned_problems_are_completed =
+                    current_date.rest || assigned <= total_solved_not_used;
                 Ok(Report {
+                    date: current_date.date,
+                    deadline: self.deadline,
+                    required_daily_pace: self.required_pace(current_date.date),
                     total_remaining,
                     total_solved,
                     total_penalty,
@@ -133,6 +254,11 @@ impl Interpreter {
                     assigned,
                     assigned_problems_are_completed,
                     total_solved_not_used,
+                    books: self.book_reports(),
+                    chapters: self.chapter_reports(),
+                    history: self.history.clone(),
+                    consistency: Consistency::new(&self.history),
+                    forecast: None,
                 })
             } else {
                 Err("Cannot generate report without beginning date.")
@@ -141,6 +267,21 @@ impl Interpreter {
             Err("Cannot generate report without setting problem goal.")
         }
     }
+    /// The history, followed by a record of the current date so far, which has no
+    /// penalty yet since penalties are only given once a date is completed.
+    pub fn days(&self) -> Vec<DayRecord> {
+        let mut days = self.history.clone();
+        days.extend(self.day_record(self.current_date_completed(), 0));
+        days
+    }
+    /// The problem `number` refers to in the current chapter, if a chapter has been begun.
+    pub fn current_problem_id(&self, number: ProblemNumber) -> Option<ProblemID> {
+        self.current_chapter
+            .map(|chapter| self.problem_id(number, chapter))
+    }
+    /// Executes a single command. A command which fails has no effect, except that
+    /// `begin <date>` and `vacation` still begin their dates, so that interpretation can
+    /// carry on past errors in the previous one.
     pub fn next_command(&mut self, cmd: Command) -> Result<(), InterpreterError> {
         match cmd {
             Command::SetProblemGoal(n) => {
@@ -149,21 +290,51 @@ impl Interpreter {
             Command::SetPenalty(n) => {
                 self.penalty = Some(n);
             }
-            Command::BeginDate(date) => {
-                self.process_current_date()?;
-                if !self.check_next_date_contiguous(&date) {
-                    return Err(InterpreterError::DateNotContiguous);
+            Command::SetDeadline(date) => {
+                self.deadline = Some(date);
+            }
+            Command::SetBookGoal(n) => {
+                if let Some(current_book) = self.current_book {
+                    self.books[current_book].goal = Some(n);
+                } else {
+                    return Err(InterpreterError::BookGoalWithoutBook);
                 }
+            }
+            Command::BeginDate(date) => {
+                // The new date is started even if the previous one was invalid, so that
+                // interpretation can carry on past the error.
+                let processed = self.process_current_date();
+                let contiguous = self.check_next_date_contiguous(&date);
 
                 self.current_date = Some(DailyInformation {
-                    date: date,
+                    date,
                     assigned: 0,
                     penalty: false,
+                    rest: false,
+                    solved: 0,
+                    required_pace: self.required_pace(date),
                 });
+
+                processed?;
+                if !contiguous {
+                    return Err(InterpreterError::DateNotContiguous);
+                }
             }
             Command::BeginChapter(n) => {
                 self.current_chapter = Some(n);
             }
+            Command::BeginBook(name) => {
+                let index = match self.books.iter().position(|book| book.name == name) {
+                    Some(index) => index,
+                    None => {
+                        self.books.push(Book { name, goal: None });
+                        self.books.len() - 1
+                    }
+                };
+                self.current_book = Some(index);
+                // chapters are scoped to a book
+                self.current_chapter = None;
+            }
             Command::AssignedAmount(n) => {
                 if let Some(current_date) = &mut self.current_date {
                     current_date.assigned += n;
@@ -173,6 +344,20 @@ impl Interpreter {
             }
             Command::FinishedAmount(n) => {
                 self.problems.finished(n)?;
+                self.count_solved(n);
+            }
+            Command::FinishedProblems(list) => {
+                if let Some(current_chapter) = self.current_chapter {
+                    let ids: Vec<ProblemID> = list
+                        .into_iter()
+                        .map(|problem_number| self.problem_id(problem_number, current_chapter))
+                        .collect();
+                    let n = ids.len() as u32;
+                    self.problems.finished_specific(ids)?;
+                    self.count_solved(n);
+                } else {
+                    return Err(InterpreterError::FinishProblemsWithoutChapter);
+                }
             }
             Command::AddProblems(mut list, skip) => {
                 if let Some(current_chapter) = self.current_chapter {
@@ -189,24 +374,24 @@ impl Interpreter {
                             }
                         }
                     }
-                    self.problems
-                        .add(list.iter().map(|&problem_number| ProblemID {
-                            number: problem_number,
-                            chapter: current_chapter,
-                        }))?;
+                    let ids: Vec<ProblemID> = list
+                        .into_iter()
+                        .map(|problem_number| self.problem_id(problem_number, current_chapter))
+                        .collect();
+                    self.problems.add(ids)?;
                 } else {
                     return Err(InterpreterError::AddProblemsWithoutChapter);
                 }
             }
             Command::AddEvenProblems(list, skip) => {
                 self.next_command(Command::AddProblems(
-                    list.iter().cloned().filter(|x| x % 2 == 0).collect(),
+                    list.into_iter().filter(|x| x.number() % 2 == 0).collect(),
                     skip,
                 ))?;
             }
             Command::AddOddProblems(list, skip) => {
                 self.next_command(Command::AddProblems(
-                    list.iter().cloned().filter(|x| x % 2 == 1).collect(),
+                    list.into_iter().filter(|x| x.number() % 2 == 1).collect(),
                     skip,
                 ))?;
             }
@@ -227,29 +412,216 @@ impl Interpreter {
                 self.total_penalty = 0;
                 self.problems.reset_remaining();
             }
+            Command::RestDay => {
+                if let Some(current_date) = &mut self.current_date {
+                    current_date.rest = true;
+                } else {
+                    return Err(InterpreterError::RestDayNoDate);
+                }
+            }
+            Command::Vacation(start, end) => {
+                if end < start {
+                    return Err(InterpreterError::VacationEndsBeforeStart(start, end));
+                }
+                // like `begin <date>`, every date is begun even if an earlier one fails
+                let mut result = Ok(());
+                for date in start.iter_days().take_while(|date| *date <= end) {
+                    result = result.and(self.next_command(Command::BeginDate(date)));
+                    self.next_command(Command::RestDay)?;
+                }
+                result?;
+            }
+            // includes are resolved while reading the input, before commands get here
+            Command::Include(_) => {
+                return Err(InterpreterError::UnhandledInclude);
+            }
+            Command::Unadd(list) => {
+                if let Some(current_chapter) = self.current_chapter {
+                    let ids: Vec<ProblemID> = list
+                        .into_iter()
+                        .map(|problem_number| self.problem_id(problem_number, current_chapter))
+                        .collect();
+                    self.problems.unadd(&ids)?;
+                } else {
+                    return Err(InterpreterError::UnaddProblemsWithoutChapter);
+                }
+            }
+            Command::Unfinish(n) => {
+                self.problems.unfinish(n)?;
+                if let Some(current_date) = &mut self.current_date {
+                    current_date.solved = current_date.solved.saturating_sub(n);
+                }
+            }
             Command::NOP => {}
         }
         Ok(())
     }
-    fn find_problem<'a, I>(
-        mut problem_list: I,
-        problem_number: u32,
-        chapter: u32,
-    ) -> Option<&'a mut Problem>
+    fn find_problem<'a, I>(mut problem_list: I, id: &ProblemID) -> Option<&'a mut Problem>
     where
         I: Iterator<Item = &'a mut Problem>,
     {
-        problem_list.find(|p| p.id.number == problem_number && p.id.chapter == chapter)
+        problem_list.find(|p| p.id == *id)
+    }
+    fn problem_id(&self, number: ProblemNumber, chapter: u32) -> ProblemID {
+        ProblemID {
+            number,
+            chapter,
+            book: self
+                .current_book
+                .map(|index| self.books[index].name.clone()),
+        }
+    }
+    fn chapter_reports(&self) -> Vec<ChapterReport> {
+        // Chapters are sorted by the order in which their books were begun, then by number.
+        let key = |id: &ProblemID| {
+            let book = id
+                .book
+                .as_ref()
+                .and_then(|name| self.books.iter().position(|book| book.name == *name));
+            (book, id.chapter)
+        };
+        let mut chapters: BTreeMap<(Option<usize>, u32), ChapterReport> = BTreeMap::new();
+        for id in &self.problems.all_added {
+            let chapter = chapters.entry(key(id)).or_insert_with(|| ChapterReport {
+                book: id.book.clone(),
+                chapter: id.chapter,
+                total_added: 0,
+                total_solved: 0,
+                total_unsolved: 0,
+                total_need_to_fix: 0,
+                percent_complete: 0.0,
+            });
+            chapter.total_added += 1;
+        }
+        for p in self.problems.all_solved_iter() {
+            let chapter = chapters.get_mut(&key(&p.id)).unwrap();
+            chapter.total_solved += 1;
+            if p.fix_status == FixStatus::NeedToFix {
+                chapter.total_need_to_fix += 1;
+            }
+        }
+        for id in &self.problems.unsolved {
+            chapters.get_mut(&key(id)).unwrap().total_unsolved += 1;
+        }
+        chapters
+            .into_values()
+            .map(|mut chapter| {
+                chapter.percent_complete =
+                    f64::from(chapter.total_solved - chapter.total_need_to_fix)
+                        / f64::from(chapter.total_added)
+                        * 100.0;
+                chapter
+            })
+            .collect()
+    }
+    fn book_reports(&self) -> Vec<BookReport> {
+        self.books
+            .iter()
+            .map(|book| {
+                let in_book = |id: &ProblemID| id.book.as_ref() == Some(&book.name);
+                let total_added = self
+                    .problems
+                    .all_added
+                    .iter()
+                    .filter(|id| in_book(id))
+                    .count();
+                let total_solved = self
+                    .problems
+                    .solved_iter()
+                    .filter(|p| in_book(&p.id))
+                    .count();
+                let total_unsolved = self
+                    .problems
+                    .unsolved
+                    .iter()
+                    .filter(|id| in_book(id))
+                    .count();
+                let total_need_to_fix = self
+                    .problems
+                    .solved_iter()
+                    .filter(|p| in_book(&p.id) && p.fix_status == FixStatus::NeedToFix)
+                    .count() as u32;
+                let total_solved = total_solved as u32;
+                BookReport {
+                    name: book.name.clone(),
+                    goal: book.goal,
+                    total_added: total_added as u32,
+                    total_solved,
+                    total_unsolved: total_unsolved as u32,
+                    total_need_to_fix,
+                    total_remaining: book
+                        .goal
+                        .map(|goal| (goal + total_need_to_fix).saturating_sub(total_solved)),
+                }
+            })
+            .collect()
     }
     fn check_next_date_contiguous(&self, date: &NaiveDate) -> bool {
         if let Some(current_date) = &self.current_date {
-            date == &current_date.date.succ()
+            current_date.date.succ_opt() == Some(*date)
         } else {
             true
         }
     }
+    fn total_remaining(&self, problem_goal: u32) -> u32 {
+        let total_need_to_fix = self
+            .problems
+            .solved_iter()
+            .filter(|p| p.fix_status == FixStatus::NeedToFix)
+            .count() as u32;
+        // more problems may have been solved than the goal calls for
+        (problem_goal + self.total_penalty + total_need_to_fix)
+            .saturating_sub(self.problems.total_solved())
+    }
+    fn required_pace(&self, date: NaiveDate) -> Option<u32> {
+        report::required_pace(
+            self.total_remaining(self.problem_goal?),
+            date,
+            self.deadline?,
+        )
+    }
+    fn count_solved(&mut self, n: u32) {
+        if let Some(current_date) = &mut self.current_date {
+            current_date.solved += n;
+        }
+    }
     fn process_current_date(&mut self) -> Result<(), InterpreterError> {
+        let penalty_before = self.total_penalty;
+        let completed = self.current_date_completed();
+        let result = self.complete_current_date();
+        if let Some(record) = self.day_record(completed, self.total_penalty - penalty_before) {
+            self.history.push(record);
+        }
+        result
+    }
+    fn current_date_completed(&self) -> bool {
+        match &self.current_date {
+            Some(current_date) => {
+                current_date.rest || current_date.assigned <= self.problems.total_solved_not_used()
+            }
+            None => false,
+        }
+    }
+    fn day_record(&self, completed: bool, penalty: u32) -> Option<DayRecord> {
+        self.current_date.as_ref().map(|current_date| DayRecord {
+            date: current_date.date,
+            assigned: current_date.assigned,
+            solved: current_date.solved,
+            rest: current_date.rest,
+            completed,
+            penalty,
+            total_remaining: self.problem_goal.map(|goal| self.total_remaining(goal)),
+            required_pace: current_date.required_pace,
+        })
+    }
+    fn complete_current_date(&mut self) -> Result<(), InterpreterError> {
         if let Some(current_date) = &self.current_date {
+            if current_date.rest {
+                if current_date.penalty {
+                    return Err(InterpreterError::PenaltyOnRestDay(current_date.date));
+                }
+                return Ok(());
+            }
             let enough_problems_to_use = self.problems.use_problems(current_date.assigned);
 
             if !enough_problems_to_use {
@@ -272,32 +644,38 @@ impl Interpreter {
     }
     fn change_need_to_fix_status(
         &mut self,
-        list: Vec<u32>,
+        list: Vec<ProblemNumber>,
         new_status: FixStatus,
     ) -> Result<(), InterpreterError> {
         if let Some(current_chapter) = self.current_chapter {
-            for problem_number in list {
-                if let Some(mut problem) = Interpreter::find_problem(
-                    self.problems.solved_iter_mut(),
-                    problem_number,
-                    current_chapter,
-                ) {
-                    if problem.fix_status != new_status {
-                        problem.fix_status = new_status;
-                    } else {
+            let ids: Vec<ProblemID> = list
+                .into_iter()
+                .map(|problem_number| self.problem_id(problem_number, current_chapter))
+                .collect();
+            // check every problem before changing any, so that a failing command has no effect
+            let mut seen = HashSet::new();
+            for id in &ids {
+                match self.problems.solved_iter().find(|p| p.id == *id) {
+                    Some(problem) if problem.fix_status == new_status || !seen.insert(id) => {
                         return Err(InterpreterError::FixStatusNotChanged(
-                            problem.id.clone(),
+                            id.clone(),
                             new_status,
                         ));
                     }
-                } else {
-                    return Err(InterpreterError::FixStatusProblemNotFound(
-                        ProblemID {
-                            number: problem_number,
-                            chapter: current_chapter,
-                        },
-                        new_status,
-                    ));
+                    Some(_) => {}
+                    None => {
+                        return Err(InterpreterError::FixStatusProblemNotFound(
+                            id.clone(),
+                            new_status,
+                        ))
+                    }
+                }
+            }
+            for id in &ids {
+                if let Some(problem) =
+                    Interpreter::find_problem(self.problems.solved_iter_mut(), id)
+                {
+                    problem.fix_status = new_status;
                 }
             }
         } else {
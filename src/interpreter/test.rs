@@ -1,3 +1,6 @@
+// The tests predate chrono deprecating `from_ymd` in favour of `from_ymd_opt`.
+#![allow(deprecated, clippy::zero_prefixed_literal)]
+
 use super::*;
 
 #[test]
@@ -78,15 +81,24 @@ fn test_cmd_assigned_amount_no_date() -> Result<(), InterpreterError> {
 }
 
 fn check_unsolved_numbers(exec: &Interpreter, expected_numbers: Vec<u32>) {
-    let unsolved: Vec<u32> = exec.problems.unsolved.iter().map(|p| p.number).collect();
+    let unsolved: Vec<u32> = exec
+        .problems
+        .unsolved
+        .iter()
+        .map(|p| p.number.number())
+        .collect();
     assert_eq!(unsolved, expected_numbers);
 }
 
+fn numbers<I: IntoIterator<Item = u32>>(list: I) -> Vec<ProblemNumber> {
+    list.into_iter().map(ProblemNumber::from).collect()
+}
+
 #[test]
 fn test_cmd_add_problems() -> Result<(), InterpreterError> {
     let mut exec = Interpreter::default();
     exec.next_command(Command::BeginChapter(8))?;
-    exec.next_command(Command::AddProblems((1..=6).collect(), None))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
     check_unsolved_numbers(&exec, vec![1, 2, 3, 4, 5, 6]);
     Ok(())
 }
@@ -95,7 +107,7 @@ fn test_cmd_add_problems() -> Result<(), InterpreterError> {
 fn test_cmd_add_even_problems() -> Result<(), InterpreterError> {
     let mut exec = Interpreter::default();
     exec.next_command(Command::BeginChapter(8))?;
-    exec.next_command(Command::AddEvenProblems((1..=6).collect(), None))?;
+    exec.next_command(Command::AddEvenProblems(numbers(1..=6), None))?;
     check_unsolved_numbers(&exec, vec![2, 4, 6]);
     Ok(())
 }
@@ -104,7 +116,7 @@ fn test_cmd_add_even_problems() -> Result<(), InterpreterError> {
 fn test_cmd_add_odd_problems() -> Result<(), InterpreterError> {
     let mut exec = Interpreter::default();
     exec.next_command(Command::BeginChapter(8))?;
-    exec.next_command(Command::AddOddProblems((1..=6).collect(), None))?;
+    exec.next_command(Command::AddOddProblems(numbers(1..=6), None))?;
     check_unsolved_numbers(&exec, vec![1, 3, 5]);
     Ok(())
 }
@@ -113,14 +125,373 @@ fn test_cmd_add_odd_problems() -> Result<(), InterpreterError> {
 fn test_cmd_add_duplicate_problem() -> Result<(), InterpreterError> {
     let mut exec = Interpreter::default();
     exec.next_command(Command::BeginChapter(8))?;
-    exec.next_command(Command::AddProblems((1..=6).collect(), None))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
     assert_eq!(
-        exec.next_command(Command::AddProblems(vec![5], None))
+        exec.next_command(Command::AddProblems(numbers(vec![5]), None))
             .unwrap_err(),
         InterpreterError::DuplicateProblemAdded(ProblemID {
-            number: 5,
+            number: ProblemNumber::from(5),
             chapter: 8,
+            book: None,
         })
     );
     Ok(())
 }
+
+#[test]
+fn test_failed_command_has_no_effect() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginChapter(8))?;
+    exec.next_command(Command::AddProblems(numbers(1..=3), None))?;
+    assert!(exec
+        .next_command(Command::AddProblems(numbers(vec![4, 3]), None))
+        .is_err());
+    assert!(exec
+        .next_command(Command::AddProblems(numbers(vec![5, 5]), None))
+        .is_err());
+    check_unsolved_numbers(&exec, vec![1, 2, 3]);
+    exec.next_command(Command::FinishedAmount(2))?;
+    assert!(exec
+        .next_command(Command::NeedToFix(numbers(vec![1, 3])))
+        .is_err());
+    assert!(exec
+        .problems
+        .solved_iter()
+        .all(|p| p.fix_status == FixStatus::Fixed));
+    Ok(())
+}
+
+#[test]
+fn test_cmd_begin_date_after_missing_penalty() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date1 = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    let date2 = NaiveDate::from_ymd_opt(2021, 7, 2).unwrap();
+    exec.next_command(Command::BeginDate(date1))?;
+    exec.next_command(Command::AssignedAmount(5))?;
+    assert_eq!(
+        exec.next_command(Command::BeginDate(date2)).unwrap_err(),
+        InterpreterError::MissingPenalty(date1)
+    );
+    assert_eq!(exec.current_date.unwrap().date, date2);
+    Ok(())
+}
+
+#[test]
+fn test_cmd_rest_day() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 1).unwrap(),
+    ))?;
+    exec.next_command(Command::AssignedAmount(5))?;
+    exec.next_command(Command::RestDay)?;
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 2).unwrap(),
+    ))?;
+    assert_eq!(exec.total_penalty, 0);
+    Ok(())
+}
+
+#[test]
+fn test_cmd_rest_day_with_penalty() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::BeginDate(date))?;
+    exec.next_command(Command::RestDay)?;
+    exec.next_command(Command::Penalty)?;
+    assert_eq!(
+        exec.next_command(Command::BeginDate(date.succ_opt().unwrap()))
+            .unwrap_err(),
+        InterpreterError::PenaltyOnRestDay(date)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cmd_vacation() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 1).unwrap(),
+    ))?;
+    exec.next_command(Command::Vacation(
+        NaiveDate::from_ymd_opt(2021, 7, 2).unwrap(),
+        NaiveDate::from_ymd_opt(2021, 7, 5).unwrap(),
+    ))?;
+    let current_date = exec.current_date.clone().unwrap();
+    assert_eq!(
+        current_date.date,
+        NaiveDate::from_ymd_opt(2021, 7, 5).unwrap()
+    );
+    assert!(current_date.rest);
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 6).unwrap(),
+    ))?;
+    Ok(())
+}
+
+#[test]
+fn test_cmd_vacation_after_missing_penalty() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::BeginDate(date))?;
+    exec.next_command(Command::AssignedAmount(5))?;
+    assert_eq!(
+        exec.next_command(Command::Vacation(
+            NaiveDate::from_ymd_opt(2021, 7, 2).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 7, 5).unwrap(),
+        ))
+        .unwrap_err(),
+        InterpreterError::MissingPenalty(date)
+    );
+    let current_date = exec.current_date.unwrap();
+    assert_eq!(
+        current_date.date,
+        NaiveDate::from_ymd_opt(2021, 7, 5).unwrap()
+    );
+    assert!(current_date.rest);
+    Ok(())
+}
+
+#[test]
+fn test_cmd_begin_book() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginBook("Physics".to_owned()))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(vec![1, 2]), None))?;
+    exec.next_command(Command::BeginBook("Math".to_owned()))?;
+    assert_eq!(
+        exec.next_command(Command::AddProblems(numbers(vec![1, 2]), None))
+            .unwrap_err(),
+        InterpreterError::AddProblemsWithoutChapter
+    );
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(vec![1, 2]), None))?;
+    assert_eq!(exec.problems.unsolved.len(), 4);
+    assert_eq!(exec.problems.unsolved[2].book, Some("Math".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn test_cmd_set_book_goal() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    assert_eq!(
+        exec.next_command(Command::SetBookGoal(30)).unwrap_err(),
+        InterpreterError::BookGoalWithoutBook
+    );
+    exec.next_command(Command::BeginBook("Physics".to_owned()))?;
+    exec.next_command(Command::SetBookGoal(30))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(vec![1, 2, 3]), None))?;
+    exec.next_command(Command::FinishedAmount(2))?;
+    let books = exec.book_reports();
+    assert_eq!(books.len(), 1);
+    assert_eq!(books[0].total_solved, 2);
+    assert_eq!(books[0].total_unsolved, 1);
+    assert_eq!(books[0].total_remaining, Some(28));
+    Ok(())
+}
+
+#[test]
+fn test_cmd_finished_problems() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginChapter(8))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
+    exec.next_command(Command::FinishedProblems(numbers(vec![2, 5])))?;
+    check_unsolved_numbers(&exec, vec![1, 3, 4, 6]);
+    assert_eq!(exec.problems.total_solved_not_used(), 2);
+    assert_eq!(
+        exec.next_command(Command::FinishedProblems(numbers(vec![5])))
+            .unwrap_err(),
+        InterpreterError::ProblemAlreadySolved(ProblemID {
+            number: ProblemNumber::from(5),
+            chapter: 8,
+            book: None,
+        })
+    );
+    assert_eq!(
+        exec.next_command(Command::FinishedProblems(numbers(vec![7])))
+            .unwrap_err(),
+        InterpreterError::FinishedProblemNotAdded(ProblemID {
+            number: ProblemNumber::from(7),
+            chapter: 8,
+            book: None,
+        })
+    );
+    // nothing is solved unless every problem can be
+    assert!(exec
+        .next_command(Command::FinishedProblems(numbers(vec![1, 7])))
+        .is_err());
+    assert!(exec
+        .next_command(Command::FinishedProblems(numbers(vec![3, 3])))
+        .is_err());
+    check_unsolved_numbers(&exec, vec![1, 3, 4, 6]);
+    assert_eq!(exec.problems.total_solved_not_used(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_chapter_reports() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginChapter(2))?;
+    exec.next_command(Command::AddProblems(numbers(1..=4), None))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(1..=2), None))?;
+    exec.next_command(Command::FinishedAmount(5))?;
+    exec.next_command(Command::NeedToFix(numbers(vec![1])))?;
+    let chapters = exec.chapter_reports();
+    assert_eq!(chapters.len(), 2);
+    assert_eq!(chapters[0].chapter, 1);
+    assert_eq!(chapters[0].total_solved, 1);
+    assert_eq!(chapters[0].total_unsolved, 1);
+    assert_eq!(chapters[0].total_need_to_fix, 1);
+    assert_eq!(chapters[0].percent_complete, 0.0);
+    assert_eq!(chapters[1].chapter, 2);
+    assert_eq!(chapters[1].percent_complete, 100.0);
+    Ok(())
+}
+
+#[test]
+fn test_history() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::SetProblemGoal(10))?;
+    exec.next_command(Command::SetPenalty(5))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
+    exec.next_command(Command::BeginDate(date))?;
+    exec.next_command(Command::AssignedAmount(2))?;
+    exec.next_command(Command::FinishedAmount(2))?;
+    exec.next_command(Command::BeginDate(date.succ_opt().unwrap()))?;
+    exec.next_command(Command::AssignedAmount(3))?;
+    exec.next_command(Command::Penalty)?;
+    assert_eq!(exec.history.len(), 1);
+    exec.next_command(Command::BeginDate(date + chrono::Duration::days(2)))?;
+    let days: Vec<(u32, u32, u32, Option<u32>)> = exec
+        .history
+        .iter()
+        .map(|day| (day.assigned, day.solved, day.penalty, day.total_remaining))
+        .collect();
+    assert_eq!(days, vec![(2, 2, 0, Some(8)), (3, 0, 5, Some(13))]);
+    Ok(())
+}
+
+#[test]
+fn test_history_solved_past_goal() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::SetProblemGoal(2))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(1..=3), None))?;
+    exec.next_command(Command::BeginDate(date))?;
+    exec.next_command(Command::FinishedAmount(3))?;
+    exec.next_command(Command::BeginDate(date.succ_opt().unwrap()))?;
+    assert_eq!(exec.history[0].total_remaining, Some(0));
+    assert_eq!(exec.report().unwrap().total_remaining, 0);
+    Ok(())
+}
+
+#[test]
+fn test_cmd_set_deadline() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::SetProblemGoal(10))?;
+    exec.next_command(Command::SetDeadline(date + chrono::Duration::days(3)))?;
+    exec.next_command(Command::BeginDate(date))?;
+    assert_eq!(exec.current_date.clone().unwrap().required_pace, Some(3));
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
+    exec.next_command(Command::AssignedAmount(2))?;
+    exec.next_command(Command::FinishedAmount(4))?;
+    exec.next_command(Command::BeginDate(date.succ_opt().unwrap()))?;
+    assert!(exec.history[0].below_required_pace());
+    assert_eq!(exec.report().unwrap().required_daily_pace, Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_consistency() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    let mut date = NaiveDate::from_ymd_opt(2021, 7, 1).unwrap();
+    exec.next_command(Command::SetPenalty(5))?;
+    exec.next_command(Command::BeginChapter(1))?;
+    exec.next_command(Command::AddProblems(numbers(1..=20), None))?;
+    // completed, completed, penalty, rest, completed, completed
+    for (completed, rest) in [
+        (true, false),
+        (true, false),
+        (false, false),
+        (false, true),
+        (true, false),
+        (true, false),
+    ] {
+        exec.next_command(Command::BeginDate(date))?;
+        exec.next_command(Command::AssignedAmount(2))?;
+        if rest {
+            exec.next_command(Command::RestDay)?;
+        } else if completed {
+            exec.next_command(Command::FinishedAmount(2))?;
+        } else {
+            exec.next_command(Command::Penalty)?;
+        }
+        date = date.succ_opt().unwrap();
+    }
+    exec.next_command(Command::BeginDate(date))?;
+    let consistency = Consistency::new(&exec.history);
+    assert_eq!(consistency.current_streak, 2);
+    assert_eq!(consistency.longest_streak, 2);
+    assert_eq!(consistency.penalty_days, 1);
+    assert_eq!(consistency.percent_completed, Some(80.0));
+    Ok(())
+}
+
+#[test]
+fn test_cmd_unadd() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginChapter(8))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
+    exec.next_command(Command::FinishedAmount(1))?;
+    exec.next_command(Command::Unadd(numbers(vec![3, 4])))?;
+    check_unsolved_numbers(&exec, vec![2, 5, 6]);
+    // removed problems can be added again
+    exec.next_command(Command::AddProblems(numbers(vec![4]), None))?;
+    check_unsolved_numbers(&exec, vec![2, 5, 6, 4]);
+    assert_eq!(
+        exec.next_command(Command::Unadd(numbers(vec![2, 1])))
+            .unwrap_err(),
+        InterpreterError::UnaddedProblemAlreadySolved(ProblemID {
+            number: ProblemNumber::from(1),
+            chapter: 8,
+            book: None,
+        })
+    );
+    check_unsolved_numbers(&exec, vec![2, 5, 6, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_cmd_unfinish() -> Result<(), InterpreterError> {
+    let mut exec = Interpreter::default();
+    exec.next_command(Command::BeginChapter(8))?;
+    exec.next_command(Command::AddProblems(numbers(1..=6), None))?;
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 1).unwrap(),
+    ))?;
+    exec.next_command(Command::AssignedAmount(2))?;
+    exec.next_command(Command::FinishedAmount(2))?;
+    exec.next_command(Command::BeginDate(
+        NaiveDate::from_ymd_opt(2021, 7, 2).unwrap(),
+    ))?;
+    exec.next_command(Command::FinishedAmount(2))?;
+    exec.next_command(Command::Unfinish(1))?;
+    check_unsolved_numbers(&exec, vec![4, 5, 6]);
+    assert_eq!(exec.current_date.as_ref().unwrap().solved, 1);
+    // problems 1 and 2 were used for the first day's assignment
+    assert_eq!(
+        exec.next_command(Command::Unfinish(2)).unwrap_err(),
+        InterpreterError::UnfinishedProblemsAlreadyUsed(1)
+    );
+    assert_eq!(
+        exec.next_command(Command::Unfinish(4)).unwrap_err(),
+        InterpreterError::NotEnoughProblemsToUnfinish
+    );
+    Ok(())
+}
@@ -1,47 +1,551 @@
+//! Tracks progress on a problem set from a log of commands.
+//!
+//! [`run`] and its variants interpret a whole log. To feed commands one at a time and
+//! inspect the state in between, use an [`Interpreter`] directly:
+//!
+//! ```
+//! use problem_tracker::{parse_command, Command, Interpreter};
+//!
+//! let mut exec = Interpreter::new();
+//! exec.next_command(Command::SetProblemGoal(10)).unwrap();
+//! exec.next_command(parse_command("begin 2021-05-22").unwrap()).unwrap();
+//! exec.next_command(parse_command("begin chapter 1").unwrap()).unwrap();
+//! exec.next_command(parse_command("add 1-3").unwrap()).unwrap();
+//! assert_eq!(exec.unsolved_problems().count(), 3);
+//! assert_eq!(exec.report().unwrap().total_remaining, 10);
+//! ```
+
+mod analysis;
 mod commands;
 mod error;
+mod fix;
+mod format;
+mod incremental;
 mod interpreter;
 mod problem;
 mod report;
+mod snapshot;
 
 #[macro_use]
 extern crate lalrpop_util;
 
-lalrpop_mod!(pub parser); // synthesized by LALRPOP
+// synthesized by LALRPOP
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    pub parser
+);
+
+use analysis::Outline;
+use chrono::NaiveDate;
+use incremental::{Checkpoint, Checkpoints};
+use snapshot::LineHasher;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 
-use error::InterpreterError;
-use interpreter::Interpreter;
-use report::Report;
-use std::io;
+pub use analysis::{analyze, Analysis, DaySummary};
+pub use commands::Command;
+pub use error::{InterpreterError, InvalidValue, SyntaxError};
+pub use fix::{apply_suggestions, suggest_fixes, Suggestion};
+pub use format::format_log;
+pub use incremental::Incremental;
+pub use interpreter::Interpreter;
+pub use problem::{FixStatus, ProblemID, ProblemNumber};
+pub use report::{
+    BookReport, ChapterReport, Consistency, DayRecord, Forecast, ForecastOptions, Report,
+    REPORT_SCHEMA_VERSION,
+};
+pub use snapshot::{Snapshot, SNAPSHOT_VERSION};
+
+/// Parses a single line of a log. Blank lines and comments parse as [`Command::NOP`].
+pub fn parse_command(line: &str) -> Result<Command, SyntaxError> {
+    parser::FullCommandParser::new()
+        .parse(line)
+        .map_err(|error| SyntaxError::new(error, line))
+}
 
 pub enum RunResult {
     Report(Report),
     ParseError {
+        file: Option<String>,
         line_num: u32,
         line: String,
+        error: SyntaxError,
     },
     RuntimeError {
+        file: Option<String>,
         line_num: u32,
         error: InterpreterError,
     },
     ReportError(String),
 }
 
+/// Options shared by `run` and `run_diagnostics`.
+#[derive(Default)]
+pub struct RunOptions {
+    /// Stop interpreting at the end of this date, ignoring any later dates, so that the
+    /// report shows the state as of that day.
+    pub as_of: Option<NaiveDate>,
+    /// Overrides the deadline set with `set deadline`.
+    pub deadline: Option<NaiveDate>,
+    pub forecast: ForecastOptions,
+}
+
+/// A piece of input, such as a file. Several inputs are interpreted one after another
+/// as if they were concatenated.
+pub struct Input<T> {
+    /// Used to report where errors are, and to resolve `include` paths relative to;
+    /// `None` for e.g. the standard input.
+    pub name: Option<String>,
+    pub reader: T,
+}
+
+impl<T: io::BufRead> Input<T> {
+    pub fn unnamed(reader: T) -> Self {
+        Input { name: None, reader }
+    }
+}
+
 pub fn run<T: io::BufRead>(input: T) -> RunResult {
-    let mut exec = Interpreter::default();
+    run_with_options(input, &RunOptions::default())
+}
+
+pub fn run_with_options<T: io::BufRead>(input: T, options: &RunOptions) -> RunResult {
+    run_inputs(vec![Input::unnamed(input)], options)
+}
+
+pub fn run_inputs<T: io::BufRead>(inputs: Vec<Input<T>>, options: &RunOptions) -> RunResult {
+    let mut session = Session::new(options, true);
+    session.interpret(inputs);
+    run_result(&session.exec, session.errors, options)
+}
+
+/// Interprets `inputs` up to the first error, and returns the interpreter's state at the
+/// end, e.g. to try out new commands against.
+pub fn interpret_inputs<T: io::BufRead>(
+    inputs: Vec<Input<T>>,
+    options: &RunOptions,
+) -> Result<Interpreter, LineError> {
+    let mut session = Session::new(options, true);
+    session.interpret(inputs);
+    match session.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(session.exec),
+    }
+}
+
+/// Like `run_inputs`, but skips the lines covered by `snapshot`, starting from its state
+/// instead. The snapshot is ignored if those lines have changed since it was taken, or if
+/// it is past `options.as_of`. Also returns a snapshot of the whole input, unless there
+/// was an error, `options.as_of` is given or the input includes other files, since those
+/// could change without the snapshot noticing.
+pub fn run_inputs_with_snapshot<T: io::BufRead>(
+    inputs: Vec<Input<T>>,
+    options: &RunOptions,
+    snapshot: Option<&Snapshot>,
+) -> (RunResult, Option<Snapshot>) {
+    let mut read_error = None;
+    let mut texts = Vec::new();
+    for input in inputs {
+        let (text, error) = read_lines(input.reader, input.name.as_deref());
+        texts.push(Input {
+            name: input.name,
+            reader: text,
+        });
+        if error.is_some() {
+            read_error = error;
+            break;
+        }
+    }
+    let inputs = texts;
+    let lines = || inputs.iter().flat_map(|input| input.reader.lines());
+    let snapshot = snapshot.filter(|snapshot| {
+        let past_as_of = match (options.as_of, snapshot.interpreter.current_date()) {
+            (Some(as_of), Some(date)) => date > as_of,
+            _ => false,
+        };
+        !past_as_of && snapshot.matches(lines())
+    });
+    let readers = inputs
+        .iter()
+        .map(|input| Input {
+            name: input.name.clone(),
+            reader: input.reader.as_bytes(),
+        })
+        .collect();
+    let mut session = Session::new(options, true);
+    if let Some(snapshot) = snapshot {
+        session.resume(snapshot);
+    }
+    session.interpret(readers);
+    session.errors.extend(read_error);
+    let failed = !session.errors.is_empty();
+    let result = run_result(&session.exec, std::mem::take(&mut session.errors), options);
+    let snapshot = match (failed, session.included, options.as_of) {
+        (false, false, None) => Some(session.snapshot()),
+        _ => None,
+    };
+    (result, snapshot)
+}
+
+/// Reads the lines of `reader` up to the first one which can't be read, e.g. because it
+/// is not valid UTF-8, which is returned as an error.
+fn read_lines<T: io::BufRead>(reader: T, name: Option<&str>) -> (String, Option<LineError>) {
+    let mut text = String::new();
+    for (line, line_num) in reader.lines().zip(1..) {
+        match line {
+            Ok(line) => {
+                text.push_str(&line);
+                text.push('\n');
+            }
+            Err(error) => {
+                let error = LineError::RuntimeError {
+                    file: name.map(str::to_owned),
+                    line_num,
+                    error: InterpreterError::ReadFailed(error.to_string()),
+                };
+                return (text, Some(error));
+            }
+        }
+    }
+    (text, None)
+}
+
+fn run_result(exec: &Interpreter, errors: Vec<LineError>, options: &RunOptions) -> RunResult {
+    match errors.into_iter().next() {
+        Some(LineError::ParseError {
+            file,
+            line_num,
+            line,
+            error,
+        }) => RunResult::ParseError {
+            file,
+            line_num,
+            line,
+            error,
+        },
+        Some(LineError::RuntimeError {
+            file,
+            line_num,
+            error,
+        }) => RunResult::RuntimeError {
+            file,
+            line_num,
+            error,
+        },
+        None => match report(exec, options) {
+            Ok(report) => RunResult::Report(report),
+            Err(msg) => RunResult::ReportError(msg),
+        },
+    }
+}
+
+/// An error tied to a single line of input. `file` is the name of the input the line
+/// came from, if it has one.
+#[derive(Clone)]
+pub enum LineError {
+    ParseError {
+        file: Option<String>,
+        line_num: u32,
+        line: String,
+        error: SyntaxError,
+    },
+    RuntimeError {
+        file: Option<String>,
+        line_num: u32,
+        error: InterpreterError,
+    },
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ParseError {
+                file,
+                line_num,
+                line,
+                error,
+            } => write!(f, "{}", error.render(file.as_deref(), *line_num, line)),
+            Self::RuntimeError {
+                file: Some(file),
+                line_num,
+                error,
+            } => write!(f, "{}, line {}: {}", file, line_num, error),
+            Self::RuntimeError {
+                file: None,
+                line_num,
+                error,
+            } => write!(f, "Line {}: {}", line_num, error),
+        }
+    }
+}
+
+pub struct Diagnostics {
+    pub errors: Vec<LineError>,
+    pub report: Result<Report, String>,
+}
+
+/// Like `run`, but skips lines that fail to parse or execute instead of stopping at the
+/// first one, and collects every error along the way. See [`Interpreter::next_command`]
+/// for what a line which fails to execute still does.
+pub fn run_diagnostics<T: io::BufRead>(input: T) -> Diagnostics {
+    run_diagnostics_with_options(input, &RunOptions::default())
+}
+
+pub fn run_diagnostics_with_options<T: io::BufRead>(input: T, options: &RunOptions) -> Diagnostics {
+    run_diagnostics_inputs(vec![Input::unnamed(input)], options)
+}
+
+pub fn run_diagnostics_inputs<T: io::BufRead>(
+    inputs: Vec<Input<T>>,
+    options: &RunOptions,
+) -> Diagnostics {
+    let mut session = Session::new(options, false);
+    session.interpret(inputs);
+    Diagnostics {
+        report: report(&session.exec, options),
+        errors: session.errors,
+    }
+}
+
+fn report(exec: &Interpreter, options: &RunOptions) -> Result<Report, String> {
+    let mut report = exec.report().map_err(|msg| msg.to_owned())?;
+    if let Some(deadline) = options.deadline {
+        report.deadline = Some(deadline);
+        report.required_daily_pace =
+            report::required_pace(report.total_remaining, report.date, deadline);
+    }
+    report.forecast = Some(report.forecast(&options.forecast));
+    Ok(report)
+}
+
+struct Session<'a> {
+    exec: Interpreter,
+    errors: Vec<LineError>,
+    options: &'a RunOptions,
+    stop_at_first_error: bool,
+    /// Canonical paths of the files currently being read, outermost first.
+    include_stack: Vec<PathBuf>,
+    /// Records the lines of the outermost inputs, if given.
+    outline: Option<&'a mut Outline>,
+    /// Records a checkpoint before every date begun in the outermost inputs, if given.
+    checkpoints: Option<&'a mut Checkpoints>,
+    /// How many inputs are being read, i.e. one more than the include depth.
+    depth: usize,
+    /// Number of lines of the outermost inputs to skip, because a snapshot covers them.
+    skip_lines: u32,
+    /// Number of lines of the outermost inputs read so far, and their hash.
+    lines_read: u32,
+    hash: LineHasher,
+    /// Whether an `include` line was read.
+    included: bool,
+}
+
+impl<'a> Session<'a> {
+    fn new(options: &'a RunOptions, stop_at_first_error: bool) -> Self {
+        Session {
+            exec: Interpreter::default(),
+            errors: Vec::new(),
+            options,
+            stop_at_first_error,
+            include_stack: Vec::new(),
+            outline: None,
+            checkpoints: None,
+            depth: 0,
+            skip_lines: 0,
+            lines_read: 0,
+            hash: LineHasher::new(),
+            included: false,
+        }
+    }
+
+    /// Starts from the state of `snapshot`, skipping the lines it covers.
+    fn resume(&mut self, snapshot: &Snapshot) {
+        self.exec = snapshot.interpreter.clone();
+        self.skip_lines = snapshot.lines;
+        self.lines_read = snapshot.lines;
+        self.hash = snapshot.hasher();
+    }
+
+    fn interpret<T: io::BufRead>(&mut self, inputs: Vec<Input<T>>) {
+        for input in inputs {
+            let path = input
+                .name
+                .as_ref()
+                .and_then(|name| fs::canonicalize(name).ok());
+            if !self.interpret_input(input.name.as_deref(), path, input.reader) {
+                break;
+            }
+        }
+    }
 
-    for (line, line_num) in input.lines().zip(1..) {
-        let line = line.unwrap();
-        if let Ok(cmd) = parser::FullCommandParser::new().parse(&line) {
-            if let Err(error) = exec.next_command(cmd) {
-                return RunResult::RuntimeError { line_num, error };
+    fn snapshot(self) -> Snapshot {
+        Snapshot::new(self.lines_read, &self.hash, self.exec)
+    }
+
+    /// Returns `false` if interpretation should stop.
+    fn interpret_input<T: io::BufRead>(
+        &mut self,
+        name: Option<&str>,
+        path: Option<PathBuf>,
+        reader: T,
+    ) -> bool {
+        if let Some(path) = &path {
+            self.include_stack.push(path.clone());
+        }
+        self.depth += 1;
+        let mut keep_going = true;
+        for (line, line_num) in reader.lines().zip(1..) {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    self.errors.push(LineError::RuntimeError {
+                        file: name.map(str::to_owned),
+                        line_num,
+                        error: InterpreterError::ReadFailed(error.to_string()),
+                    });
+                    // the rest of this input can't be read, but the next ones may be
+                    keep_going = !self.stop_at_first_error;
+                    break;
+                }
+            };
+            if self.depth == 1 && self.skip_lines > 0 {
+                self.skip_lines -= 1;
+                continue;
+            }
+            let parsed = parse_command(&line);
+            if self.depth == 1 {
+                if let (Some(checkpoints), Ok(Command::BeginDate(_))) =
+                    (self.checkpoints.as_deref_mut(), &parsed)
+                {
+                    checkpoints.push(Checkpoint {
+                        snapshot: Snapshot::new(self.lines_read, &self.hash, self.exec.clone()),
+                        errors: self.errors.clone(),
+                    });
+                }
+                self.lines_read += 1;
+                self.hash.write_line(&line);
+            }
+            match parsed {
+                Ok(Command::Include(included)) => {
+                    self.included = true;
+                    match self.open_include(name, &included) {
+                        Ok((included_name, included_path, file)) => {
+                            keep_going = self.interpret_input(
+                                Some(&included_name),
+                                Some(included_path),
+                                BufReader::new(file),
+                            );
+                        }
+                        Err(error) => self.errors.push(LineError::RuntimeError {
+                            file: name.map(str::to_owned),
+                            line_num,
+                            error,
+                        }),
+                    }
+                }
+                Ok(mut cmd) => {
+                    let mut last = false;
+                    if let Some(as_of) = self.options.as_of {
+                        match &mut cmd {
+                            Command::BeginDate(date) | Command::Vacation(date, _)
+                                if *date > as_of =>
+                            {
+                                keep_going = false;
+                            }
+                            // a vacation which goes past `as_of` ends there, along with the log
+                            Command::Vacation(_, end) if *end > as_of => {
+                                *end = as_of;
+                                last = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                    if keep_going {
+                        // the problems an `add` adds go to the back of the unsolved ones
+                        let unsolved = match (&self.outline, self.depth, &cmd) {
+                            (
+                                Some(_),
+                                1,
+                                Command::AddProblems(..)
+                                | Command::AddEvenProblems(..)
+                                | Command::AddOddProblems(..),
+                            ) => Some(self.exec.unsolved_problems().count()),
+                            _ => None,
+                        };
+                        if let (Some(outline), 1) = (self.outline.as_deref_mut(), self.depth) {
+                            outline.record(line_num, &cmd, &self.exec);
+                        }
+                        match self.exec.next_command(cmd) {
+                            Ok(()) => {
+                                if let (Some(outline), Some(unsolved)) =
+                                    (self.outline.as_deref_mut(), unsolved)
+                                {
+                                    outline.record_added(
+                                        line_num,
+                                        self.exec.unsolved_problems().skip(unsolved),
+                                    );
+                                }
+                            }
+                            Err(error) => self.errors.push(LineError::RuntimeError {
+                                file: name.map(str::to_owned),
+                                line_num,
+                                error,
+                            }),
+                        }
+                    }
+                    if last {
+                        keep_going = false;
+                    }
+                }
+                Err(error) => {
+                    self.errors.push(LineError::ParseError {
+                        file: name.map(str::to_owned),
+                        line_num,
+                        line,
+                        error,
+                    });
+                }
             }
-        } else {
-            return RunResult::ParseError { line_num, line };
+            if self.stop_at_first_error && !self.errors.is_empty() {
+                keep_going = false;
+            }
+            if !keep_going {
+                break;
+            }
+        }
+        if path.is_some() {
+            self.include_stack.pop();
         }
+        self.depth -= 1;
+        keep_going
     }
-    match exec.report() {
-        Ok(report) => RunResult::Report(report),
-        Err(msg) => RunResult::ReportError(msg.to_owned()),
+
+    /// Resolves `included` relative to the directory of the including file (or the
+    /// current directory if it has no name) and opens it.
+    fn open_include(
+        &self,
+        name: Option<&str>,
+        included: &str,
+    ) -> Result<(String, PathBuf, File), InterpreterError> {
+        let dir = name
+            .and_then(|name| Path::new(name).parent())
+            .unwrap_or_else(|| Path::new(""));
+        let included_name = dir.join(included);
+        let display_name = included_name.display().to_string();
+        let file = File::open(&included_name).map_err(|e| InterpreterError::IncludeFailed {
+            path: display_name.clone(),
+            reason: e.to_string(),
+        })?;
+        let path =
+            fs::canonicalize(&included_name).map_err(|e| InterpreterError::IncludeFailed {
+                path: display_name.clone(),
+                reason: e.to_string(),
+            })?;
+        if self.include_stack.contains(&path) {
+            return Err(InterpreterError::IncludeCycle(display_name));
+        }
+        Ok((display_name, path, file))
     }
 }
@@ -1,15 +1,536 @@
-use problem_tracker::{run, RunResult};
-use std::io;
+use chrono::NaiveDate;
+use problem_tracker::{
+    apply_suggestions, format_log, interpret_inputs, parse_command, run_diagnostics_inputs,
+    run_inputs, run_inputs_with_snapshot, suggest_fixes, Command, Diagnostics, Incremental, Input,
+    Interpreter, InterpreterError, Report, RunOptions, RunResult, Snapshot,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::time::Duration;
+
+/// How often `watch` checks the file for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+enum Format {
+    Text,
+    Json,
+}
+
+enum Mode {
+    Report,
+    History,
+    /// Only print diagnostics.
+    Check,
+    /// Rewrite the files in canonical form.
+    Fmt,
+    /// Print the report again whenever the file changes.
+    Watch,
+    /// Ask for the day's work and append it to the file.
+    Log,
+    /// Insert or remove `penalty` lines to fix the errors about them.
+    Fix,
+}
+
+struct Options {
+    mode: Mode,
+    format: Format,
+    all_errors: bool,
+    warn_pace: bool,
+    /// In check mode, also fail if no report can be generated.
+    strict: bool,
+    /// In fmt mode, only report which files are not formatted.
+    check: bool,
+    /// In fix mode, print the changes as a diff instead of writing them.
+    dry_run: bool,
+    /// File to resume from, and to save the state to afterwards.
+    snapshot: Option<String>,
+    /// The date to log in log mode, instead of today.
+    date: Option<NaiveDate>,
+    run: RunOptions,
+    files: Vec<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        mode: Mode::Report,
+        format: Format::Text,
+        all_errors: false,
+        warn_pace: false,
+        strict: false,
+        check: false,
+        dry_run: false,
+        snapshot: None,
+        date: None,
+        run: RunOptions::default(),
+        files: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1).peekable();
+    let mode = match args.peek().map(String::as_str) {
+        Some("history") => Some(Mode::History),
+        Some("check") => Some(Mode::Check),
+        Some("fmt") => Some(Mode::Fmt),
+        Some("watch") => Some(Mode::Watch),
+        Some("log") => Some(Mode::Log),
+        Some("fix") => Some(Mode::Fix),
+        _ => None,
+    };
+    if let Some(mode) = mode {
+        options.mode = mode;
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("text") => Format::Text,
+                    Some("json") => Format::Json,
+                    Some(other) => return Err(format!("Unknown format \"{}\".", other)),
+                    None => return Err("Missing value for --format.".to_owned()),
+                }
+            }
+            "--all-errors" => options.all_errors = true,
+            "--warn-pace" => options.warn_pace = true,
+            "--strict" => options.strict = true,
+            "--check" => options.check = true,
+            "--dry-run" => options.dry_run = true,
+            "--snapshot" => {
+                options.snapshot = Some(args.next().ok_or("Missing value for --snapshot.")?)
+            }
+            "--date" => options.date = Some(parse_date(args.next(), "--date")?),
+            "--as-of" => options.run.as_of = Some(parse_date(args.next(), "--as-of")?),
+            "--deadline" => options.run.deadline = Some(parse_date(args.next(), "--deadline")?),
+            "--window" => {
+                let days = args.next().ok_or("Missing value for --window.")?;
+                options.run.forecast.window = days
+                    .parse()
+                    .ok()
+                    .filter(|&days| days > 0)
+                    .ok_or(format!("Invalid number of days \"{}\".", days))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown argument \"{}\".", arg)),
+            _ => options.files.push(arg),
+        }
+    }
+    if options.snapshot.is_some()
+        && (options.all_errors || matches!(options.mode, Mode::Check | Mode::Watch | Mode::Log))
+    {
+        return Err("--snapshot cannot be used with --all-errors, check, watch or log.".to_owned());
+    }
+    match options.mode {
+        Mode::Watch if options.files.len() != 1 => {
+            return Err("watch takes exactly one file.".to_owned())
+        }
+        Mode::Log if options.files.len() != 1 => {
+            return Err("log takes exactly one file.".to_owned())
+        }
+        _ => {}
+    }
+    Ok(options)
+}
+
+fn parse_date(arg: Option<String>, flag: &str) -> Result<NaiveDate, String> {
+    let date = arg.ok_or(format!("Missing value for {}.", flag))?;
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date \"{}\"; expected y-m-d.", date))
+}
+
+fn print_report(report: &Report, options: &Options) {
+    if options.warn_pace {
+        for day in report
+            .history
+            .iter()
+            .filter(|day| day.below_required_pace())
+        {
+            eprintln!(
+                "Warning: {} problems assigned on {}, below the required pace of {} problems/day.",
+                day.assigned,
+                day.date,
+                day.required_pace.unwrap()
+            );
+        }
+    }
+    match (&options.format, &options.mode) {
+        (Format::Text, Mode::History) => print!("{}", report.history_text()),
+        (Format::Text, _) => println!("{}", report),
+        (Format::Json, _) => println!("{}", report.to_json()),
+    }
+}
+
+/// Reads the snapshot at `path`. A missing snapshot is not an error, since there is none
+/// before the first run; an unreadable one is ignored with a warning.
+fn load_snapshot(path: &str) -> Option<Snapshot> {
+    let json = std::fs::read_to_string(path).ok()?;
+    Snapshot::from_json(&json)
+        .map_err(|msg| eprintln!("Warning: ignoring snapshot {}: {}", path, msg))
+        .ok()
+}
+
+/// Opens the files given on the command line, or the standard input if there are none.
+fn open_inputs(files: &[String]) -> Result<Vec<Input<Box<dyn BufRead>>>, String> {
+    if files.is_empty() {
+        return Ok(vec![Input::unnamed(Box::new(BufReader::new(io::stdin())))]);
+    }
+    files
+        .iter()
+        .map(|path| {
+            let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+            Ok(Input {
+                name: Some(path.clone()),
+                reader: Box::new(BufReader::new(file)) as Box<dyn BufRead>,
+            })
+        })
+        .collect()
+}
+
+/// Formats each file in place, or the standard input to the standard output if there are
+/// no files. With `--check`, nothing is written and the files which would change are listed.
+/// Returns `false` if any input failed to parse or, with `--check`, was not formatted.
+fn format_files(options: &Options) -> Result<bool, String> {
+    let mut ok = true;
+    for input in open_inputs(&options.files)? {
+        let name = input.name.clone();
+        let mut original = String::new();
+        let mut reader = input.reader;
+        reader
+            .read_to_string(&mut original)
+            .map_err(|e| format!("Could not read input: {}", e))?;
+        let formatted = match format_log(Input {
+            name: name.clone(),
+            reader: original.as_bytes(),
+        }) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for error in &errors {
+                    eprintln!("{}", error);
+                }
+                ok = false;
+                continue;
+            }
+        };
+        match (name, options.check) {
+            (Some(name), true) if formatted != original => {
+                println!("{}", name);
+                ok = false;
+            }
+            (None, true) => ok &= formatted == original,
+            (Some(name), false) if formatted != original => std::fs::write(&name, formatted)
+                .map_err(|e| format!("Could not write {}: {}", name, e))?,
+            (None, false) => print!("{}", formatted),
+            _ => {}
+        }
+    }
+    Ok(ok)
+}
+
+/// Clears the terminal and prints the diagnostics and report for `path` every time its
+/// contents change, until interrupted.
+fn watch(path: &str, options: &Options) -> ! {
+    let mut incremental = Incremental::new();
+    let mut last = None;
+    loop {
+        let contents = std::fs::read_to_string(path);
+        let changed = match (&contents, &last) {
+            (Ok(text), Some(Ok(last_text))) => text != last_text,
+            _ => true,
+        };
+        if changed {
+            // clear the screen and move the cursor to the top left
+            print!("\x1b[2J\x1b[H");
+            match &contents {
+                Ok(text) => {
+                    let input = Input {
+                        name: Some(path.to_owned()),
+                        reader: text.as_bytes(),
+                    };
+                    print_diagnostics(incremental.run(input, &options.run), options);
+                }
+                Err(e) => println!("Could not read {}: {}", path, e),
+            }
+            last = Some(contents);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Asks for the work done on `date` (today by default), checks it against the file at
+/// `path`, and appends it to the file.
+fn log(path: &str, date: Option<NaiveDate>) -> Result<(), String> {
+    let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+    let input = Input {
+        name: Some(path.to_owned()),
+        reader: BufReader::new(file),
+    };
+    let exec = interpret_inputs(vec![input], &RunOptions::default())
+        .map_err(|error| format!("{}\nFix the file before logging.", error))?;
+    let mut log = Log {
+        exec,
+        commands: Vec::new(),
+        answers: io::stdin().lock().lines(),
+    };
+    log.begin(date)?;
+    println!("Logging {} in {}.", date, path);
+    log.ask("Problems assigned [0]:", |answer| match answer {
+        "" | "0" => Ok(None),
+        _ => answer
+            .parse()
+            .map(|n| Some(Command::AssignedAmount(n)))
+            .map_err(|_| "Expected a number.".to_owned()),
+    })?;
+    log.ask("Problems finished [0]:", |answer| match answer {
+        "" | "0" => Ok(None),
+        _ => answer
+            .parse()
+            .map(|n| Some(Command::FinishedAmount(n)))
+            .map_err(|_| "Expected a number.".to_owned()),
+    })?;
+    log.ask(
+        "Problems that need fixing, e.g. 3, 5-7 [none]:",
+        |answer| match answer {
+            "" => Ok(None),
+            _ => parse_command(&format!("need to fix {}", answer))
+                .map(Some)
+                .map_err(|error| error.message()),
+        },
+    )?;
+    if log.commands.is_empty() {
+        println!("Nothing to log.");
+        return Ok(());
+    }
+    let mut text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    for cmd in &log.commands {
+        text.push_str(&format!("{}\n", cmd));
+    }
+    std::fs::write(path, text).map_err(|e| format!("Could not write {}: {}", path, e))?;
+    println!("Appended to {}:", path);
+    for cmd in &log.commands {
+        println!("{}", cmd);
+    }
+    Ok(())
+}
+
+/// The commands to append to a file, checked against the interpreter's state at its end.
+struct Log<I> {
+    exec: Interpreter,
+    commands: Vec<Command>,
+    answers: I,
+}
+
+impl<I: Iterator<Item = io::Result<String>>> Log<I> {
+    /// Executes `cmd` and keeps it, unless it fails.
+    fn try_command(&mut self, cmd: Command) -> Result<(), InterpreterError> {
+        let mut exec = self.exec.clone();
+        exec.next_command(cmd.clone())?;
+        self.exec = exec;
+        self.commands.push(cmd);
+        Ok(())
+    }
+
+    /// Asks `question` until the answer is parsed into a command which succeeds, or into
+    /// `None` for nothing to log. Fails if the input ends.
+    fn ask<F>(&mut self, question: &str, parse: F) -> Result<(), String>
+    where
+        F: Fn(&str) -> Result<Option<Command>, String>,
+    {
+        loop {
+            print!("{} ", question);
+            io::stdout().flush().unwrap();
+            let answer = match self.answers.next() {
+                Some(Ok(answer)) => answer,
+                _ => return Err("Nothing was logged.".to_owned()),
+            };
+            match parse(answer.trim()) {
+                Ok(Some(cmd)) => match self.try_command(cmd) {
+                    Ok(()) => return Ok(()),
+                    Err(error) => println!("{}", error),
+                },
+                Ok(None) => return Ok(()),
+                Err(msg) => println!("{}", msg),
+            }
+        }
+    }
+
+    /// Begins every date up to `date`, giving a penalty for the current date if its
+    /// assigned work was not completed.
+    fn begin(&mut self, date: NaiveDate) -> Result<(), String> {
+        let mut next = match self.exec.current_date() {
+            Some(current) if current > date => {
+                return Err(format!("The file already has {}, after {}.", current, date))
+            }
+            Some(current) => current.succ_opt().unwrap(),
+            None => date,
+        };
+        while next <= date {
+            match self.try_command(Command::BeginDate(next)) {
+                Ok(()) => {}
+                Err(InterpreterError::MissingPenalty(_)) => {
+                    self.try_command(Command::Penalty)
+                        .and_then(|()| self.try_command(Command::BeginDate(next)))
+                        .map_err(|error| error.to_string())?;
+                }
+                Err(error) => return Err(error.to_string()),
+            }
+            next = next.succ_opt().unwrap();
+        }
+        Ok(())
+    }
+}
+
+fn print_diagnostics(diagnostics: Diagnostics, options: &Options) {
+    for error in &diagnostics.errors {
+        println!("{}\n", error);
+    }
+    match diagnostics.report {
+        Ok(report) => print_report(&report, options),
+        Err(msg) => println!("{}", msg),
+    }
+}
+
+/// Fixes the `penalty` lines of each file in place, or of the standard input to the
+/// standard output if there are no files. With `--dry-run`, the changes are printed as a
+/// unified diff instead.
+fn fix_files(options: &Options) -> Result<(), String> {
+    for input in open_inputs(&options.files)? {
+        let mut original = String::new();
+        let mut reader = input.reader;
+        reader
+            .read_to_string(&mut original)
+            .map_err(|e| format!("Could not read input: {}", e))?;
+        let suggestions = suggest_fixes(Input {
+            name: input.name.clone(),
+            reader: original.as_bytes(),
+        });
+        let fixed = apply_suggestions(&original, &suggestions);
+        let name = input.name.as_deref().unwrap_or("<stdin>");
+        match (&input.name, options.dry_run) {
+            (_, true) if !suggestions.is_empty() => print!(
+                "{}",
+                similar::TextDiff::from_lines(&original, &fixed)
+                    .unified_diff()
+                    .header(name, name)
+            ),
+            (Some(path), false) if !suggestions.is_empty() => {
+                std::fs::write(path, fixed)
+                    .map_err(|e| format!("Could not write {}: {}", path, e))?;
+                eprintln!("{}: applied {} fixes.", path, suggestions.len());
+            }
+            (None, false) => print!("{}", fixed),
+            _ => {}
+        }
+    }
+    Ok(())
+}
 
 fn main() {
-    let stdin = io::stdin();
-    match run(stdin.lock()) {
-        RunResult::Report(report) => println!("{}", report),
-        RunResult::ParseError { line_num, line } => {
-            eprintln!("Could not parse line {}: \"{}\"", line_num, line);
+    let options = parse_args().unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        std::process::exit(2);
+    });
+    if let Mode::Fix = options.mode {
+        if let Err(msg) = fix_files(&options) {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Mode::Log = options.mode {
+        if let Err(msg) = log(&options.files[0], options.date) {
+            eprintln!("{}", msg);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Mode::Watch = options.mode {
+        watch(&options.files[0], &options);
+    }
+    if let Mode::Fmt = options.mode {
+        match format_files(&options) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                std::process::exit(1);
+            }
+        }
+    }
+    let inputs = open_inputs(&options.files).unwrap_or_else(|msg| {
+        eprintln!("{}", msg);
+        std::process::exit(1);
+    });
+    if let Mode::Check = options.mode {
+        let diagnostics = run_diagnostics_inputs(inputs, &options.run);
+        for error in &diagnostics.errors {
+            eprintln!("{}", error);
+        }
+        let mut failed = !diagnostics.errors.is_empty();
+        if let (true, Err(msg)) = (options.strict, &diagnostics.report) {
+            eprintln!("{}", msg);
+            failed = true;
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if options.all_errors {
+        let diagnostics = run_diagnostics_inputs(inputs, &options.run);
+        for error in &diagnostics.errors {
+            eprintln!("{}", error);
+        }
+        let mut failed = !diagnostics.errors.is_empty();
+        match diagnostics.report {
+            Ok(report) => print_report(&report, &options),
+            Err(msg) => {
+                eprintln!("{}", msg);
+                failed = true;
+            }
+        }
+        if failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+    let result = match &options.snapshot {
+        Some(path) => {
+            let snapshot = load_snapshot(path);
+            let (result, snapshot) =
+                run_inputs_with_snapshot(inputs, &options.run, snapshot.as_ref());
+            if let Some(snapshot) = snapshot {
+                if let Err(e) = std::fs::write(path, snapshot.to_json()) {
+                    eprintln!("Warning: could not write snapshot {}: {}", path, e);
+                }
+            }
+            result
+        }
+        None => run_inputs(inputs, &options.run),
+    };
+    match result {
+        RunResult::Report(report) => print_report(&report, &options),
+        RunResult::ParseError {
+            file,
+            line_num,
+            line,
+            error,
+        } => {
+            eprintln!("{}", error.render(file.as_deref(), line_num, &line));
+            std::process::exit(1);
+        }
+        RunResult::RuntimeError {
+            file: Some(file),
+            line_num,
+            error,
+        } => {
+            eprintln!("{}, line {}: {}", file, line_num, error);
             std::process::exit(1);
         }
-        RunResult::RuntimeError { line_num, error } => {
+        RunResult::RuntimeError {
+            file: None,
+            line_num,
+            error,
+        } => {
             eprintln!("Line {}: {}", line_num, error);
             std::process::exit(1);
         }
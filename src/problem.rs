@@ -1,24 +1,203 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
+use std::str::FromStr;
 
+/// A problem label such as `17`, `17a` or `P4.12`: an optional alphabetic prefix, one or
+/// more dot-separated numbers and an optional letter suffix.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProblemNumber {
+    prefix: String,
+    /// Never empty.
+    parts: Vec<u32>,
+    suffix: Option<char>,
+}
+
+impl ProblemNumber {
+    /// The alphabetic prefix, e.g. `P` for `P4.12`; empty if there is none.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The dot-separated numbers, e.g. `[4, 12]` for `P4.12`.
+    pub fn parts(&self) -> &[u32] {
+        &self.parts
+    }
+
+    /// The letter suffix, e.g. `a` for `17a`.
+    pub fn suffix(&self) -> Option<char> {
+        self.suffix
+    }
+
+    /// The last numeric part of the label, e.g. 12 for `P4.12`. This is the part which
+    /// `add even` and `add odd` look at.
+    pub fn number(&self) -> u32 {
+        *self.parts.last().unwrap()
+    }
+
+    /// The label which follows this one in a range: the next letter if there is a
+    /// suffix, or else the next last number.
+    pub fn succ(&self) -> Option<ProblemNumber> {
+        let mut next = self.clone();
+        match self.suffix {
+            Some('z') | Some('Z') => return None,
+            Some(suffix) => next.suffix = Some(std::char::from_u32(suffix as u32 + 1)?),
+            None => *next.parts.last_mut().unwrap() = self.number().checked_add(1)?,
+        }
+        Some(next)
+    }
+
+    /// Expands `start-end` into every label in between. Either the last number or the
+    /// letter suffix may vary (`3-7`, `P4.1-P4.5`, `17a-17d`), but not both; letter
+    /// suffixes must be of the same case. Returns `None` if the labels don't form such a
+    /// range.
+    pub fn range(start: &ProblemNumber, end: &ProblemNumber) -> Option<Vec<ProblemNumber>> {
+        if start.prefix != end.prefix || start.parts.len() != end.parts.len() {
+            return None;
+        }
+        match (start.suffix, end.suffix) {
+            (None, None) => {
+                let last = start.parts.len() - 1;
+                if start.parts[..last] != end.parts[..last] || start.number() > end.number() {
+                    return None;
+                }
+                Some(
+                    (start.number()..=end.number())
+                        .map(|n| {
+                            let mut number = start.clone();
+                            number.parts[last] = n;
+                            number
+                        })
+                        .collect(),
+                )
+            }
+            (Some(first), Some(last)) => {
+                let same_case = (first.is_ascii_lowercase() && last.is_ascii_lowercase())
+                    || (first.is_ascii_uppercase() && last.is_ascii_uppercase());
+                if start.parts != end.parts || !same_case || first > last {
+                    return None;
+                }
+                Some(
+                    (first..=last)
+                        .map(|suffix| ProblemNumber {
+                            suffix: Some(suffix),
+                            ..start.clone()
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<u32> for ProblemNumber {
+    fn from(number: u32) -> Self {
+        ProblemNumber {
+            prefix: String::new(),
+            parts: vec![number],
+            suffix: None,
+        }
+    }
+}
+
+impl FromStr for ProblemNumber {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let numbers_start = s.find(|c: char| c.is_ascii_digit()).ok_or(())?;
+        let (prefix, rest) = s.split_at(numbers_start);
+        if !prefix.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(());
+        }
+        let (rest, suffix) = match rest.chars().last() {
+            Some(c) if c.is_ascii_alphabetic() => (&rest[..rest.len() - 1], Some(c)),
+            _ => (rest, None),
+        };
+        let parts = rest
+            .split('.')
+            .map(|part| part.parse().map_err(|_| ()))
+            .collect::<Result<Vec<u32>, ()>>()?;
+        Ok(ProblemNumber {
+            prefix: prefix.to_owned(),
+            parts,
+            suffix,
+        })
+    }
+}
+
+impl fmt::Display for ProblemNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.prefix)?;
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", part)?;
+        }
+        if let Some(suffix) = self.suffix {
+            write!(f, "{}", suffix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Plain numbers are serialized as JSON numbers, as they were before labels were
+/// supported; any other label is serialized as a string.
+impl Serialize for ProblemNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.prefix.is_empty() && self.parts.len() == 1 && self.suffix.is_none() {
+            serializer.serialize_u32(self.number())
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ProblemNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Serialized {
+            Number(u32),
+            Label(String),
+        }
+        match Serialized::deserialize(deserializer)? {
+            Serialized::Number(number) => Ok(ProblemNumber::from(number)),
+            Serialized::Label(label) => label
+                .parse()
+                .map_err(|_| D::Error::custom(format!("invalid problem label \"{}\"", label))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ProblemID {
-    pub number: u32,
+    pub number: ProblemNumber,
     pub chapter: u32,
+    /// `None` for problems added before any `begin book` command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub book: Option<String>,
 }
 
 impl fmt::Display for ProblemID {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "problem {} of chapter {}", self.number, self.chapter)
+        write!(f, "problem {} of chapter {}", self.number, self.chapter)?;
+        if let Some(book) = &self.book {
+            write!(f, " of \"{}\"", book)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Problem {
     pub id: ProblemID,
     pub fix_status: FixStatus,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Whether a solved problem needs to be fixed, as set by `need to fix` and `fixed`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum FixStatus {
     Fixed,
     NeedToFix,
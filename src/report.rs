@@ -1,8 +1,21 @@
 use super::problem::ProblemID;
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug)]
+/// Version of the JSON report schema. Bump this whenever a field is renamed,
+/// removed or changes meaning; adding new fields does not require a bump.
+pub const REPORT_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize)]
 pub struct Report {
+    /// The current (last begun) date.
+    pub date: NaiveDate,
+    /// Set with `set deadline`, unless overridden by `RunOptions::deadline`.
+    pub deadline: Option<NaiveDate>,
+    /// Problems per day needed from the current date through the deadline (inclusive)
+    /// to finish; `None` if there is no deadline or it has passed.
+    pub required_daily_pace: Option<u32>,
     pub total_remaining: u32,
     pub total_solved: u32,
     pub total_penalty: u32,
@@ -12,6 +25,199 @@ pub struct Report {
     pub assigned_problems_are_completed: bool,
     pub assigned: u32,
     pub total_solved_not_used: u32,
+    pub books: Vec<BookReport>,
+    pub chapters: Vec<ChapterReport>,
+    pub history: Vec<DayRecord>,
+    pub consistency: Consistency,
+    /// Filled in by `run` and friends according to `RunOptions::forecast`.
+    pub forecast: Option<Forecast>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookReport {
+    pub name: String,
+    pub goal: Option<u32>,
+    pub total_added: u32,
+    pub total_solved: u32,
+    pub total_unsolved: u32,
+    pub total_need_to_fix: u32,
+    /// `None` if no goal was set for the book.
+    pub total_remaining: Option<u32>,
+}
+
+/// Unlike the global totals, the counts for a chapter include problems solved before
+/// `reset remaining`.
+#[derive(Debug, Serialize)]
+pub struct ChapterReport {
+    pub book: Option<String>,
+    pub chapter: u32,
+    pub total_added: u32,
+    pub total_solved: u32,
+    pub total_unsolved: u32,
+    pub total_need_to_fix: u32,
+    /// Percentage of the added problems which are solved and don't need to be fixed.
+    pub percent_complete: f64,
+}
+
+/// What happened on a single (completed) date.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DayRecord {
+    pub date: NaiveDate,
+    pub assigned: u32,
+    /// Problems marked as finished on this date.
+    pub solved: u32,
+    pub rest: bool,
+    /// Whether the assigned work was completed. Always true on rest days.
+    pub completed: bool,
+    /// Penalty added at the end of this date; 0 if the assigned work was completed.
+    pub penalty: u32,
+    /// Total remaining at the end of this date; `None` if no problem goal was set yet.
+    pub total_remaining: Option<u32>,
+    /// Problems per day needed to finish by the deadline, as of the start of this date.
+    pub required_pace: Option<u32>,
+}
+
+impl DayRecord {
+    /// Whether less work was assigned on this date than needed to finish by the deadline.
+    pub fn below_required_pace(&self) -> bool {
+        !self.rest && matches!(self.required_pace, Some(pace) if self.assigned < pace)
+    }
+}
+
+/// Statistics over the completed dates. Rest days neither extend nor break a streak.
+#[derive(Debug, Default, Serialize)]
+pub struct Consistency {
+    /// Number of consecutive dates, up to the last completed one, on which the assigned
+    /// work was completed.
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub penalty_days: u32,
+    /// Percentage of dates (other than rest days) on which the assigned work was
+    /// completed; `None` if there are no such dates yet.
+    pub percent_completed: Option<f64>,
+}
+
+impl Consistency {
+    pub fn new(history: &[DayRecord]) -> Self {
+        let mut consistency = Consistency::default();
+        let mut days = 0;
+        for day in history.iter().filter(|day| !day.rest) {
+            days += 1;
+            if day.completed {
+                consistency.current_streak += 1;
+                consistency.longest_streak =
+                    consistency.longest_streak.max(consistency.current_streak);
+            } else {
+                consistency.current_streak = 0;
+                consistency.penalty_days += 1;
+            }
+        }
+        if days > 0 {
+            consistency.percent_completed =
+                Some(f64::from(days - consistency.penalty_days) / f64::from(days) * 100.0);
+        }
+        consistency
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ForecastOptions {
+    /// Number of most recent completed days to compute the solve rate from.
+    pub window: u32,
+}
+
+impl Default for ForecastOptions {
+    fn default() -> Self {
+        ForecastOptions { window: 7 }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Forecast {
+    /// Number of days the solve rate was computed from; less than the requested window
+    /// if there isn't that much history yet.
+    pub window: u32,
+    /// Average number of problems solved per day over the window.
+    pub daily_rate: f64,
+    /// Date on which the total remaining would reach zero at `daily_rate`; `None` if
+    /// nothing was solved during the window, or if the date is too far off to represent.
+    pub projected_completion: Option<NaiveDate>,
+}
+
+/// Problems per day needed to solve `remaining` problems from `date` through `deadline`
+/// (inclusive); `None` if the deadline has passed.
+pub(crate) fn required_pace(remaining: u32, date: NaiveDate, deadline: NaiveDate) -> Option<u32> {
+    let days_left = (deadline - date).num_days() + 1;
+    if days_left > 0 {
+        Some((f64::from(remaining) / days_left as f64).ceil() as u32)
+    } else {
+        None
+    }
+}
+
+#[derive(Serialize)]
+struct VersionedReport<'a> {
+    version: u32,
+    #[serde(flatten)]
+    report: &'a Report,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&VersionedReport {
+            version: REPORT_SCHEMA_VERSION,
+            report: self,
+        })
+        .expect("report serialization cannot fail")
+    }
+
+    pub fn forecast(&self, options: &ForecastOptions) -> Forecast {
+        let recent = &self.history[self.history.len().saturating_sub(options.window as usize)..];
+        let window = recent.len() as u32;
+        let solved: u32 = recent.iter().map(|day| day.solved).sum();
+        let daily_rate = if window > 0 {
+            f64::from(solved) / f64::from(window)
+        } else {
+            0.0
+        };
+        let remaining = f64::from(self.total_remaining);
+        let projected_completion = if self.total_remaining == 0 {
+            Some(self.date)
+        } else if daily_rate > 0.0 {
+            Duration::try_days((remaining / daily_rate).ceil() as i64)
+                .and_then(|days| self.date.checked_add_signed(days))
+        } else {
+            None
+        };
+        Forecast {
+            window,
+            daily_rate,
+            projected_completion,
+        }
+    }
+
+    /// Renders the day-by-day history as a table.
+    pub fn history_text(&self) -> String {
+        let mut text = format!(
+            "{:<10}  {:>8}  {:>6}  {:>7}  {:>9}\n",
+            "Date", "Assigned", "Solved", "Penalty", "Remaining"
+        );
+        for day in &self.history {
+            let remaining = day
+                .total_remaining
+                .map_or_else(|| "-".to_owned(), |n| n.to_string());
+            text += &format!(
+                "{:<10}  {:>8}  {:>6}  {:>7}  {:>9}{}\n",
+                day.date,
+                day.assigned,
+                day.solved,
+                day.penalty,
+                remaining,
+                if day.rest { "  (rest day)" } else { "" }
+            );
+        }
+        text
+    }
 }
 
 impl fmt::Display for Report {
@@ -32,6 +238,44 @@ impl fmt::Display for Report {
         writeln!(f, "Total Penalty: {}", self.total_penalty)?;
         writeln!(f, "Total Need to Fix: {}", self.total_need_to_fix)?;
 
+        writeln!(
+            f,
+            "\nCurrent Streak: {} days",
+            self.consistency.current_streak
+        )?;
+        writeln!(
+            f,
+            "Longest Streak: {} days",
+            self.consistency.longest_streak
+        )?;
+        writeln!(f, "Penalty Days: {}", self.consistency.penalty_days)?;
+        if let Some(percent) = self.consistency.percent_completed {
+            writeln!(f, "Days Completed: {:.0}%", percent)?;
+        }
+
+        if let Some(deadline) = self.deadline {
+            match self.required_daily_pace {
+                Some(pace) => {
+                    writeln!(f, "\nDeadline: {} ({} problems/day needed)", deadline, pace)?
+                }
+                None => writeln!(f, "\nDeadline: {} (passed)", deadline)?,
+            }
+        }
+
+        // without any completed days, there is no rate to forecast from
+        if let Some(forecast) = self
+            .forecast
+            .as_ref()
+            .filter(|forecast| forecast.window > 0)
+        {
+            writeln!(f, "\nForecast (last {} days):", forecast.window)?;
+            writeln!(f, "Solve Rate: {:.1} problems/day", forecast.daily_rate)?;
+            match forecast.projected_completion {
+                Some(date) => writeln!(f, "Projected Completion: {}", date)?,
+                None => writeln!(f, "Projected Completion: never")?,
+            }
+        }
+
         writeln!(f, "\nNeed to Fix Problems:")?;
         for p in &self.need_to_fix_problems {
             writeln!(f, "{}", p)?;
@@ -41,6 +285,44 @@ impl fmt::Display for Report {
         for p in &self.unsolved_problems {
             writeln!(f, "{}", p)?;
         }
+
+        if !self.chapters.is_empty() {
+            writeln!(f, "\nChapters:")?;
+            for chapter in &self.chapters {
+                write!(f, "Chapter {}", chapter.chapter)?;
+                if let Some(book) = &chapter.book {
+                    write!(f, " of \"{}\"", book)?;
+                }
+                writeln!(
+                    f,
+                    ": {} added, {} solved, {} unsolved, {} need to fix ({:.0}% complete)",
+                    chapter.total_added,
+                    chapter.total_solved,
+                    chapter.total_unsolved,
+                    chapter.total_need_to_fix,
+                    chapter.percent_complete
+                )?;
+            }
+        }
+
+        if !self.books.is_empty() {
+            writeln!(f, "\nBooks:")?;
+            for book in &self.books {
+                write!(
+                    f,
+                    "\"{}\": {} added, {} solved, {} unsolved, {} need to fix",
+                    book.name,
+                    book.total_added,
+                    book.total_solved,
+                    book.total_unsolved,
+                    book.total_need_to_fix
+                )?;
+                if let (Some(goal), Some(remaining)) = (book.goal, book.total_remaining) {
+                    write!(f, ", {} remaining of goal {}", remaining, goal)?;
+                }
+                writeln!(f)?;
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,88 @@
+use super::interpreter::Interpreter;
+use serde::{Deserialize, Serialize};
+
+/// Version of the snapshot format. Snapshots of any other version are rejected, since
+/// the interpreter's state is not kept compatible between versions.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// The state of an [`Interpreter`] after reading the first lines of a log, so that a later
+/// run can carry on from there instead of reading the whole log again.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    version: u32,
+    /// Number of lines read, counting every input (but not included files).
+    pub lines: u32,
+    /// Hash of the lines read, to tell whether they have changed since.
+    hash: u64,
+    pub interpreter: Interpreter,
+}
+
+impl Snapshot {
+    pub(crate) fn new(lines: u32, hash: &LineHasher, interpreter: Interpreter) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            lines,
+            hash: hash.finish(),
+            interpreter,
+        }
+    }
+
+    /// Whether this snapshot was taken from a log starting with `lines`.
+    pub(crate) fn matches<'a, I>(&self, lines: I) -> bool
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut hash = LineHasher::new();
+        let mut count = 0;
+        for line in lines.take(self.lines as usize) {
+            hash.write_line(line);
+            count += 1;
+        }
+        count == self.lines && hash.finish() == self.hash
+    }
+
+    /// Where to carry on hashing the lines after the ones this snapshot covers.
+    pub(crate) fn hasher(&self) -> LineHasher {
+        LineHasher(self.hash)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("snapshot serialization cannot fail")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == SNAPSHOT_VERSION as u64 => {
+                serde_json::from_value(value).map_err(|e| e.to_string())
+            }
+            Some(version) => Err(format!(
+                "Snapshot version {} is not supported; expected {}.",
+                version, SNAPSHOT_VERSION
+            )),
+            None => Err("Snapshot has no version.".to_owned()),
+        }
+    }
+}
+
+/// Hashes lines of input with FNV-1a, which unlike `std`'s hasher is guaranteed to give
+/// the same result in every build, so that hashes can be compared between runs.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LineHasher(u64);
+
+impl LineHasher {
+    pub(crate) fn new() -> Self {
+        LineHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write_line(&mut self, line: &str) {
+        for byte in line.bytes().chain(Some(b'\n')) {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
@@ -1,6 +1,12 @@
 extern crate problem_tracker;
 
-use problem_tracker::{run, RunResult};
+use chrono::NaiveDate;
+use problem_tracker::{
+    analyze, apply_suggestions, format_log, parse_command, run, run_diagnostics,
+    run_diagnostics_inputs, run_inputs, run_inputs_with_snapshot, run_with_options, suggest_fixes,
+    Command, FixStatus, ForecastOptions, Incremental, Input, Interpreter, InterpreterError,
+    LineError, ProblemNumber, RunOptions, RunResult, Snapshot, Suggestion,
+};
 
 #[test]
 fn test_parse_error() {
@@ -11,3 +17,736 @@ fn test_parse_error() {
         panic!();
     }
 }
+
+#[test]
+fn test_report_json() {
+    let program = b"set problem goal 10\nbegin 2021-05-22\nbegin chapter 11\nadd 42-44\nfinished 1 problem\nneed to fix 42";
+    if let RunResult::Report(report) = run(&program[..]) {
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["version"], problem_tracker::REPORT_SCHEMA_VERSION);
+        assert_eq!(json["total_remaining"], 10);
+        assert_eq!(
+            json["unsolved_problems"],
+            serde_json::json!([{"number": 43, "chapter": 11}, {"number": 44, "chapter": 11}])
+        );
+        assert_eq!(
+            json["need_to_fix_problems"],
+            serde_json::json!([{"number": 42, "chapter": 11}])
+        );
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_run_diagnostics_collects_all_errors() {
+    let program = b"set problem goal 10\nbegin 2021-05-22\nbad command\nbegin chapter 1\nadd 1-3\nassigned 2 problems\nbegin 2021-05-23\nadd 3\nfinished 1 problem\nbegin 2021-05-24";
+    let diagnostics = run_diagnostics(&program[..]);
+    let lines: Vec<u32> = diagnostics
+        .errors
+        .iter()
+        .map(|e| match e {
+            LineError::ParseError { line_num, .. } => *line_num,
+            LineError::RuntimeError { line_num, .. } => *line_num,
+        })
+        .collect();
+    assert_eq!(lines, vec![3, 7, 8]);
+    assert_eq!(diagnostics.report.unwrap().total_solved, 1);
+}
+
+#[test]
+fn test_parse_error_span_and_expected_tokens() {
+    let program = b"set problem goal 400\nassigned 5 probs";
+    if let RunResult::ParseError {
+        line_num,
+        line,
+        error,
+        ..
+    } = run(&program[..])
+    {
+        assert_eq!((error.start, error.end), (11, 16));
+        assert_eq!(
+            error.message(),
+            "expected `problem` or `problems`, found `probs`"
+        );
+        assert_eq!(
+            error.render(None, line_num, &line),
+            "error: expected `problem` or `problems`, found `probs`\n --> line 2:12\n  |\n2 | assigned 5 probs\n  |            ^^^^^"
+        );
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_missing_quoted_argument() {
+    assert_eq!(
+        parse_command("begin book").unwrap_err().message(),
+        "expected a quoted name, found end of line"
+    );
+    assert_eq!(
+        parse_command("include may.txt").unwrap_err().message(),
+        "expected a quoted path, found `may`"
+    );
+}
+
+#[test]
+fn test_alphanumeric_problem_labels() {
+    let program = b"set problem goal 10\nbegin 2021-05-22\nbegin chapter 1\nadd 17a-17c, P4.1-P4.2, 5\nadd odd P5.1-P5.4";
+    if let RunResult::Report(report) = run(&program[..]) {
+        let labels: Vec<String> = report
+            .unsolved_problems
+            .iter()
+            .map(|p| p.number.to_string())
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["17a", "17b", "17c", "P4.1", "P4.2", "5", "P5.1", "P5.3"]
+        );
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_invalid_problem_range() {
+    let program = b"begin chapter 1\nadd 1, 17a-18c";
+    if let RunResult::ParseError { error, .. } = run(&program[..]) {
+        assert_eq!((error.start, error.end), (7, 14));
+        assert_eq!(error.message(), "expected a valid range, found `17a-18c`");
+    } else {
+        panic!();
+    }
+    assert_eq!(
+        parse_command("add 17A-17c").unwrap_err().message(),
+        "expected a valid range, found `17A-17c`"
+    );
+    assert_eq!(
+        parse_command("add 99999999999a").unwrap_err().message(),
+        "expected a valid problem label, found `99999999999a`"
+    );
+    assert_eq!(
+        parse_command("add 99999999999").unwrap_err().message(),
+        "expected a smaller number, found `99999999999`"
+    );
+}
+
+#[test]
+fn test_invalid_date() {
+    let error = parse_command("begin 2021-02-30").unwrap_err();
+    assert_eq!((error.start, error.end), (6, 16));
+    assert_eq!(error.message(), "expected a valid date, found `2021-02-30`");
+    assert_eq!(
+        parse_command("begin 2021-05-22 extra")
+            .unwrap_err()
+            .message(),
+        "expected end of line, found `extra`"
+    );
+    assert_eq!(
+        parse_command("vacation 2021-05-22 extra")
+            .unwrap_err()
+            .message(),
+        "expected `to`, found `extra`"
+    );
+}
+
+#[test]
+fn test_run_as_of() {
+    let program = b"set problem goal 10\nset penalty 5\nbegin 2021-05-22\nbegin chapter 1\nadd 1-5\nassigned 2 problems\nfinished 2 problems\nbegin 2021-05-23\nassigned 3 problems\npenalty\nbegin 2021-05-24\nbad command";
+    let options = RunOptions {
+        as_of: Some(NaiveDate::from_ymd_opt(2021, 5, 23).unwrap()),
+        ..RunOptions::default()
+    };
+    if let RunResult::Report(report) = run_with_options(&program[..], &options) {
+        assert_eq!(report.assigned, 3);
+        assert_eq!(report.total_penalty, 0);
+        assert_eq!(report.total_remaining, 8);
+    } else {
+        panic!();
+    }
+
+    // stopping in the middle of a vacation
+    let program = b"set problem goal 10\nbegin 2021-05-23\nvacation 2021-05-24 to 2021-05-30\nassigned 3 problems";
+    let options = RunOptions {
+        as_of: Some(NaiveDate::from_ymd_opt(2021, 5, 25).unwrap()),
+        ..RunOptions::default()
+    };
+    if let RunResult::Report(report) = run_with_options(&program[..], &options) {
+        assert_eq!(report.date, options.as_of.unwrap());
+        assert_eq!(report.assigned, 0);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_run_inputs_reports_file_name() {
+    let first = Input {
+        name: Some("spring.txt".to_owned()),
+        reader: &b"set problem goal 10\nbegin chapter 1\nadd 1-3"[..],
+    };
+    let second = Input {
+        name: Some("summer.txt".to_owned()),
+        reader: &b"begin 2021-05-22\nadd 3"[..],
+    };
+    if let RunResult::RuntimeError { file, line_num, .. } =
+        run_inputs(vec![first, second], &RunOptions::default())
+    {
+        assert_eq!(file.as_deref(), Some("summer.txt"));
+        assert_eq!(line_num, 2);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_unreadable_line() {
+    let program = b"set problem goal 10\nbegin 2021-05-22\n\xff\nbegin 2021-05-23";
+    let is_read_error = |result: RunResult| {
+        matches!(
+            result,
+            RunResult::RuntimeError {
+                line_num: 3,
+                error: InterpreterError::ReadFailed(_),
+                ..
+            }
+        )
+    };
+    assert!(is_read_error(run(&program[..])));
+    let (result, snapshot) = run_inputs_with_snapshot(
+        vec![Input::unnamed(&program[..])],
+        &RunOptions::default(),
+        None,
+    );
+    assert!(is_read_error(result));
+    assert!(snapshot.is_none());
+}
+
+#[test]
+fn test_include() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_include");
+    std::fs::create_dir_all(dir.join("months")).unwrap();
+    std::fs::write(
+        dir.join("main.txt"),
+        "set problem goal 10\nbegin 2021-05-22\ninclude \"months/may.txt\"\nfinished 1 problem",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("months/may.txt"),
+        "begin chapter 1\nadd 1-3\ninclude \"may.txt\"",
+    )
+    .unwrap();
+    let main = dir.join("main.txt").display().to_string();
+    let input = Input {
+        name: Some(main.clone()),
+        reader: std::io::BufReader::new(std::fs::File::open(&main).unwrap()),
+    };
+    let diagnostics = run_diagnostics_inputs(vec![input], &RunOptions::default());
+    assert_eq!(diagnostics.errors.len(), 1);
+    if let LineError::RuntimeError {
+        file,
+        line_num,
+        error,
+    } = &diagnostics.errors[0]
+    {
+        assert!(file.as_ref().unwrap().ends_with("may.txt"));
+        assert_eq!(*line_num, 3);
+        assert!(matches!(error, InterpreterError::IncludeCycle(_)));
+    } else {
+        panic!();
+    }
+    let report = diagnostics.report.unwrap();
+    assert_eq!(report.total_solved, 1);
+    assert_eq!(report.unsolved_problems.len(), 2);
+}
+
+#[test]
+fn test_forecast() {
+    let program = b"set problem goal 10\nbegin chapter 1\nadd 1-5\nbegin 2021-05-22\nfinished 3 problems\nbegin 2021-05-23\nfinished 1 problem\nbegin 2021-05-24";
+    let options = RunOptions {
+        deadline: Some(NaiveDate::from_ymd_opt(2021, 5, 26).unwrap()),
+        forecast: ForecastOptions { window: 7 },
+        ..RunOptions::default()
+    };
+    if let RunResult::Report(report) = run_with_options(&program[..], &options) {
+        assert!(report.to_string().contains("Forecast (last 2 days):"));
+        assert_eq!(report.required_daily_pace, Some(2));
+        let forecast = report.forecast.unwrap();
+        assert_eq!(forecast.window, 2);
+        assert_eq!(forecast.daily_rate, 2.0);
+        assert_eq!(
+            forecast.projected_completion,
+            Some(NaiveDate::from_ymd_opt(2021, 5, 27).unwrap())
+        );
+    } else {
+        panic!();
+    }
+    // no forecast before the first date is completed
+    let program =
+        b"set problem goal 10\nbegin chapter 1\nadd 1-5\nbegin 2021-05-22\nfinished 3 problems";
+    if let RunResult::Report(report) = run_with_options(&program[..], &options) {
+        assert!(!report.to_string().contains("Forecast"));
+        assert_eq!(report.forecast.unwrap().window, 0);
+    } else {
+        panic!();
+    }
+    // a projected date too far off to represent
+    let program = b"set problem goal 400000000\nbegin chapter 1\nadd 1\nbegin 2021-05-22\nfinished 1 problem\nbegin 2021-05-23";
+    if let RunResult::Report(report) = run_with_options(&program[..], &options) {
+        assert!(report.to_string().contains("Projected Completion: never"));
+        assert_eq!(report.forecast.unwrap().projected_completion, None);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_check_subcommand() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_check");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("problems.txt");
+    std::fs::write(&path, "begin chapter 1\nadd 1-3").unwrap();
+    let check = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_problem-tracker"))
+            .arg("check")
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap()
+    };
+    let output = check(&[]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(!check(&["--strict"]).status.success());
+    std::fs::write(&path, "begin chapter 1\nadd 1-3\nadd 3").unwrap();
+    assert!(!check(&[]).status.success());
+}
+
+#[test]
+fn test_format_log() {
+    let program = b"set  problem goal 10  #goal\n\n# chapter one\nbegin chapter 1\nadd 1,2,3, 5, 7-8,9\nadd odd 17a, 17b skipping 4\nassigned 1 problems";
+    assert_eq!(
+        format_log(Input::unnamed(&program[..])).ok().unwrap(),
+        "set problem goal 10 # goal\n\n# chapter one\nbegin chapter 1\nadd 1-3, 5, 7-9\nadd odd 17a-17b skipping 4\nassigned 1 problem\n"
+    );
+    let errors = format_log(Input::unnamed(&b"begin chapter 1\nbad command"[..]))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        errors[0],
+        LineError::ParseError { line_num: 2, .. }
+    ));
+}
+
+#[test]
+fn test_analyze() {
+    let program = b"set problem goal 10\nbegin chapter 1\nadd 1-5\nbegin 2021-05-22\nassigned 2 problems\nfinished 1, 2\nbegin 2021-05-23\nneed to fix 2\nfinished 3\nbegin chapter 2\nadd 2";
+    let analysis = analyze(Input::unnamed(&program[..]), &RunOptions::default());
+    assert!(analysis.errors.is_empty());
+    let first = analysis.day(4).unwrap();
+    assert_eq!((first.day.solved, first.total_solved), (2, 2));
+    let second = analysis.day(7).unwrap();
+    assert_eq!((second.day.solved, second.total_solved), (1, 3));
+    assert!(analysis.day(5).is_none());
+    assert_eq!(analysis.definition(8, &ProblemNumber::from(2)), Some(3));
+    assert_eq!(analysis.definition(8, &ProblemNumber::from(3)), None);
+
+    // only problems which are actually added are defined
+    let program = b"set problem goal 10\nbegin chapter 1\nadd even 1-4\nadd odd 1-4\nadd 5-6 skipping 6\nadd 6, 2\nadd 6\nbegin 2021-05-22\nfinished 3, 6";
+    let analysis = analyze(Input::unnamed(&program[..]), &RunOptions::default());
+    assert_eq!(analysis.errors.len(), 1);
+    assert_eq!(analysis.definition(9, &ProblemNumber::from(3)), Some(4));
+    assert_eq!(analysis.definition(9, &ProblemNumber::from(6)), Some(7));
+}
+
+#[test]
+fn test_interpreter_api() {
+    let mut exec = Interpreter::new();
+    for line in [
+        "set problem goal 10",
+        "begin book \"Calculus\"",
+        "begin chapter 3",
+    ] {
+        exec.next_command(parse_command(line).unwrap()).unwrap();
+    }
+    assert_eq!(exec.current_book(), Some("Calculus"));
+    assert_eq!(exec.current_chapter(), Some(3));
+    exec.next_command(Command::AddProblems(vec![ProblemNumber::from(1)], None))
+        .unwrap();
+    assert_eq!(
+        exec.next_command(parse_command("add 1").unwrap()),
+        Err(InterpreterError::DuplicateProblemAdded(
+            exec.unsolved_problems().next().unwrap().clone()
+        ))
+    );
+    assert!(matches!(
+        exec.next_command(parse_command("fixed 1").unwrap()),
+        Err(InterpreterError::FixStatusProblemNotFound(
+            _,
+            FixStatus::Fixed
+        ))
+    ));
+    assert!(exec.report().is_err());
+    assert_eq!(parse_command("# comment"), Ok(Command::NOP));
+    assert_eq!(
+        parse_command("add 1-").unwrap_err().message(),
+        "expected a number or a problem label, found end of line"
+    );
+    let list = problem_tracker::parser::ProblemListParser::new()
+        .parse("P4.12a-P4.12b")
+        .unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[1].prefix(), "P");
+    assert_eq!(list[1].parts(), &[4, 12]);
+    assert_eq!(list[1].suffix(), Some('b'));
+}
+
+#[test]
+fn test_snapshot() {
+    let program = "set problem goal 10\nbegin chapter 1\nadd 1-5\nbegin 2021-05-22\nassigned 2 problems\nfinished 2 problems\n";
+    let (_, snapshot) = run_inputs_with_snapshot(
+        vec![Input::unnamed(program.as_bytes())],
+        &RunOptions::default(),
+        None,
+    );
+    let snapshot = Snapshot::from_json(&snapshot.unwrap().to_json()).unwrap();
+    assert_eq!(snapshot.lines, 6);
+    assert_eq!(snapshot.interpreter.unsolved_problems().count(), 3);
+
+    let resume = |program: &str| {
+        let (result, next) = run_inputs_with_snapshot(
+            vec![Input::unnamed(program.as_bytes())],
+            &RunOptions::default(),
+            Some(&snapshot),
+        );
+        match (result, run(program.as_bytes())) {
+            (RunResult::Report(resumed), RunResult::Report(full)) => {
+                assert_eq!(resumed.to_json(), full.to_json());
+            }
+            _ => panic!(),
+        }
+        next.unwrap().lines
+    };
+    // new lines appended
+    let appended = format!("{}begin 2021-05-23\nfinished 3, 5\n", program);
+    assert_eq!(resume(&appended), 8);
+    // earlier lines changed, so the snapshot is stale and the whole log is interpreted again
+    let changed = appended.replace("set problem goal 10", "set problem goal 20");
+    let (result, next) = run_inputs_with_snapshot(
+        vec![Input::unnamed(changed.as_bytes())],
+        &RunOptions::default(),
+        Some(&snapshot),
+    );
+    match result {
+        RunResult::Report(report) => assert_eq!(report.total_remaining, 16),
+        _ => panic!(),
+    }
+    assert_eq!(next.unwrap().lines, 8);
+
+    let json = snapshot
+        .to_json()
+        .replacen("\"version\":2", "\"version\":0", 1);
+    assert!(Snapshot::from_json(&json).is_err());
+
+    // included files can change without the snapshot noticing, so none is taken
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_snapshot");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("inc.txt"), "add 1-5").unwrap();
+    let main = dir.join("main.txt");
+    std::fs::write(
+        &main,
+        "set problem goal 10\nbegin 2021-05-22\nbegin chapter 1\ninclude \"inc.txt\"",
+    )
+    .unwrap();
+    let input = Input {
+        name: Some(main.display().to_string()),
+        reader: std::io::BufReader::new(std::fs::File::open(&main).unwrap()),
+    };
+    let (result, snapshot) = run_inputs_with_snapshot(vec![input], &RunOptions::default(), None);
+    assert!(matches!(result, RunResult::Report(_)));
+    assert!(snapshot.is_none());
+}
+
+#[test]
+fn test_incremental() {
+    let mut incremental = Incremental::new();
+    let mut check = |program: &str| {
+        let resumed = incremental.run(Input::unnamed(program.as_bytes()), &RunOptions::default());
+        let full = run_diagnostics(program.as_bytes());
+        let lines = |errors: &[LineError]| -> Vec<String> {
+            errors.iter().map(|error| error.to_string()).collect()
+        };
+        assert_eq!(lines(&resumed.errors), lines(&full.errors));
+        assert_eq!(
+            resumed.report.unwrap().to_json(),
+            full.report.unwrap().to_json()
+        );
+    };
+    let mut program = "set problem goal 10\nbegin chapter 1\nadd 1-9\nadd 3\n".to_owned();
+    for day in 22..28 {
+        program.push_str(&format!(
+            "begin 2021-05-{}\nassigned 1 problem\nfinished 1 problem\n",
+            day
+        ));
+        check(&program);
+    }
+    // an edit near the end, then one before every checkpoint
+    check(&program.replace(
+        "begin 2021-05-27\nassigned 1",
+        "begin 2021-05-27\nassigned 2",
+    ));
+    check(&program.replace("add 1-9", "add 1-8"));
+    check(&program[..program.len() / 2]);
+
+    let mut incremental = Incremental::new();
+    incremental.run(Input::unnamed(program.as_bytes()), &RunOptions::default());
+    program.push_str("need to fix 2\n");
+    let analysis = incremental.analyze(Input::unnamed(program.as_bytes()), &RunOptions::default());
+    let line_num = program.lines().count() as u32;
+    assert_eq!(
+        analysis.definition(line_num, &ProblemNumber::from(2)),
+        Some(3)
+    );
+    assert_eq!(analysis.day(5).unwrap().day.solved, 1);
+}
+
+#[test]
+fn test_watch_subcommand() {
+    use std::io::Read;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_watch");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("problems.txt");
+    std::fs::write(&path, "set problem goal 10\nbegin 2021-05-22").unwrap();
+    let mut watch = std::process::Command::new(env!("CARGO_BIN_EXE_problem-tracker"))
+        .arg("watch")
+        .arg(&path)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // read the output on another thread, so that waiting for it can time out
+    let mut stdout = watch.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        while let Ok(n) = stdout.read(&mut buffer) {
+            if n == 0 {
+                break;
+            }
+            if sender.send(buffer[..n].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    let mut output = String::new();
+    let mut wait_for = |text: &str| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !output.contains(text) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(timeout) {
+                Ok(bytes) => output.push_str(&String::from_utf8(bytes).unwrap()),
+                Err(_) => {
+                    watch.kill().unwrap();
+                    panic!("timed out waiting for {:?} in {:?}", text, output);
+                }
+            }
+        }
+        output.clone()
+    };
+
+    wait_for("Total Remaining: 10");
+    std::fs::write(&path, "set problem goal 10\nbegin 2021-05-22\nadd 1").unwrap();
+    let output = wait_for("Cannot add problems without beginning chapter.");
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    let screens: Vec<&str> = output.split("\x1b[2J\x1b[H").skip(1).collect();
+    assert_eq!(screens.len(), 2);
+    assert!(screens[0].contains("Total Remaining: 10"));
+    assert!(screens[1].contains("Cannot add problems without beginning chapter."));
+}
+
+#[test]
+fn test_log_subcommand() {
+    use std::io::Write;
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_log");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("problems.txt");
+    let program = "set problem goal 10\nset penalty 2\nbegin 2021-05-22\nbegin chapter 1\nadd 1-5\nassigned 2 problems";
+    std::fs::write(&path, program).unwrap();
+    let mut log = std::process::Command::new(env!("CARGO_BIN_EXE_problem-tracker"))
+        .args(["log", "--date", "2021-05-23"])
+        .arg(&path)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // finishing 9 problems fails, so it is asked again
+    log.stdin
+        .take()
+        .unwrap()
+        .write_all(b"3\n9\n2\n2\n")
+        .unwrap();
+    assert!(log.wait_with_output().unwrap().status.success());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(
+            "{}\npenalty\nbegin 2021-05-23\nassigned 3 problems\nfinished 2 problems\nneed to fix 2\n",
+            program
+        )
+    );
+}
+
+#[test]
+fn test_fix_penalties() {
+    let program = "set problem goal 10\nset penalty 2\nbegin chapter 1\nadd 1-9\nbegin 2021-05-22\nassigned 2 problems\nbegin 2021-05-23\nassigned 1 problem\nfinished 1 problem\npenalty\nbegin 2021-05-24\nvacation 2021-05-25 to 2021-05-26";
+    let suggestions = suggest_fixes(Input::unnamed(program.as_bytes()));
+    assert_eq!(
+        suggestions,
+        vec![
+            Suggestion::Insert {
+                line_num: 7,
+                line: "penalty".to_owned()
+            },
+            Suggestion::Remove { line_num: 10 },
+        ]
+    );
+    let fixed = apply_suggestions(program, &suggestions);
+    assert_eq!(
+        fixed,
+        program
+            .replace("\npenalty\n", "\n")
+            .replace("problems\nbegin", "problems\npenalty\nbegin")
+    );
+    assert!(run_diagnostics(fixed.as_bytes()).errors.is_empty());
+
+    // line endings are kept
+    let program = program.replace('\n', "\r\n");
+    assert_eq!(
+        apply_suggestions(&program, &suggestions),
+        fixed.replace('\n', "\r\n")
+    );
+    assert_eq!(apply_suggestions(&program, &[]), program);
+}
+
+#[test]
+fn test_fix_subcommand() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_fix");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("problems.txt");
+    let fix = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_problem-tracker"))
+            .arg("fix")
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap()
+    };
+    let program = "set problem goal 10\r\nbegin chapter 1\r\nadd 1-3\r\nbegin 2021-05-22\r\nassigned 2 problems\r\nbegin 2021-05-23\r\n";
+    std::fs::write(
+        &path,
+        program.replace("begin 2021-05-23", "penalty\r\nbegin 2021-05-23"),
+    )
+    .unwrap();
+    let output = fix(&["--dry-run"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    std::fs::write(&path, program).unwrap();
+    assert!(!fix(&["--dry-run"]).stdout.is_empty());
+    assert!(fix(&[]).status.success());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        program.replace("begin 2021-05-23", "penalty\r\nbegin 2021-05-23")
+    );
+}
+
+#[cfg(feature = "lsp")]
+#[test]
+fn test_language_server() {
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::process::Stdio;
+
+    let mut server = std::process::Command::new(env!("CARGO_BIN_EXE_problem-tracker-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = server.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let mut send = |message: Value| {
+        let body = message.to_string();
+        write!(stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        stdin.flush().unwrap();
+    };
+    let mut receive = || -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            stdout.read_line(&mut header).unwrap();
+            match header.trim_end() {
+                "" => break,
+                header => {
+                    if let Some(value) = header.strip_prefix("Content-Length: ") {
+                        length = value.parse().unwrap();
+                    }
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    };
+
+    send(
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+    );
+    assert_eq!(receive()["result"]["capabilities"]["hoverProvider"], true);
+    send(json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}));
+
+    // invalid parameters are ignored in notifications and answered with an error in requests
+    send(json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {}}));
+    send(json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {}}));
+    let response = receive();
+    assert_eq!(response["id"], 2);
+    assert_eq!(response["error"]["code"], -32602);
+
+    let uri = "file:///tmp/problems.txt";
+    let text =
+        "set problem goal 10\nbegin 2021-05-22\nbegin chapter 1\nadd 1-2\nadd 2\nfinished 1\n# éa";
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {"textDocument": {"uri": uri, "languageId": "problem-tracker", "version": 1, "text": text}}
+    }));
+    let notification = receive();
+    assert_eq!(notification["method"], "textDocument/publishDiagnostics");
+    let diagnostics = notification["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 4);
+
+    send(json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "textDocument/definition",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 5, "character": 9}}
+    }));
+    assert_eq!(receive()["result"]["range"]["start"]["line"], 3);
+    // the cursor just after a non-ASCII character
+    send(json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "textDocument/definition",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 6, "character": 3}}
+    }));
+    assert_eq!(receive()["result"], Value::Null);
+
+    send(json!({"jsonrpc": "2.0", "id": 5, "method": "shutdown"}));
+    assert_eq!(receive()["id"], 5);
+    send(json!({"jsonrpc": "2.0", "method": "exit"}));
+    assert!(server.wait().unwrap().success());
+}